@@ -0,0 +1,238 @@
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::history::{History, SessionRecord};
+use crate::state::{keys_precision, merge_ngrams, KeysStats, MIN_OCCURRENCES};
+use crate::words::{word_list, wrap};
+
+// Characters and n-grams practiced at once
+const WEAK_CHARS: usize = 6;
+const WEAK_NGRAMS: usize = 6;
+
+// How much more likely a word is drawn for each weak character or n-gram it contains
+const BOOST: f32 = 8.0;
+
+// Share of the words replaced by a target no word of the list contains
+const DRILL_RATE: f64 = 0.15;
+
+// What the history says needs work, with a weight above 0
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Targets {
+    pub chars: Vec<(char, f32)>,
+    pub ngrams: Vec<(String, f32)>,
+}
+
+impl Targets {
+    // Weak characters weigh their error rate. Weak n-grams weigh how much slower than the
+    // average n-gram they are, plus twice their error rate.
+    pub fn from_history(sessions: &[SessionRecord]) -> Targets {
+        let mut keys_stats: KeysStats = KeysStats::new();
+        let mut ngrams = Vec::new();
+        for session in sessions {
+            for (key, total, valid) in &session.keys_stats {
+                let entry = keys_stats.entry(*key).or_insert((0, 0));
+                entry.0 += total;
+                entry.1 += valid;
+            }
+            merge_ngrams(&mut ngrams, &session.ngrams);
+        }
+
+        let mut chars: Vec<(char, f32)> = keys_precision(&keys_stats)
+            .into_iter()
+            .filter(|(c, precision)| {
+                !c.is_whitespace() && *precision < 1.0 && keys_stats[c].0 >= MIN_OCCURRENCES as i32
+            })
+            .map(|(c, precision)| (c, 1.0 - precision))
+            .collect();
+        chars.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        chars.truncate(WEAK_CHARS);
+
+        let timed: Vec<f32> = ngrams
+            .iter()
+            .filter(|s| s.timed >= MIN_OCCURRENCES)
+            .filter_map(|s| s.mean_latency())
+            .collect();
+        let average = timed.iter().sum::<f32>() / timed.len().max(1) as f32;
+
+        let mut ngrams: Vec<(String, f32)> = ngrams
+            .into_iter()
+            .filter(|s| s.count >= MIN_OCCURRENCES)
+            .map(|s| {
+                let slowness = match s.mean_latency() {
+                    Some(latency) if s.timed >= MIN_OCCURRENCES => latency / average - 1.0,
+                    _ => 0.0,
+                };
+                let weight = slowness.max(0.0) + 2.0 * s.error_rate();
+                (s.ngram, weight)
+            })
+            .filter(|(_, weight)| *weight > 0.0)
+            .collect();
+        ngrams.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        ngrams.truncate(WEAK_NGRAMS);
+
+        Targets { chars, ngrams }
+    }
+
+    fn score(&self, word: &str) -> f32 {
+        let chars: f32 = self
+            .chars
+            .iter()
+            .map(|(c, weight)| word.matches(*c).count() as f32 * weight)
+            .sum();
+        let ngrams: f32 = self
+            .ngrams
+            .iter()
+            .map(|(ngram, weight)| word.matches(ngram.as_str()).count() as f32 * weight)
+            .sum();
+        chars + ngrams
+    }
+
+    // Targets no word contains, typed on their own
+    fn drills(&self, words: &[&str]) -> Vec<String> {
+        let chars = self.chars.iter().map(|(c, _)| c.to_string());
+        let ngrams = self.ngrams.iter().map(|(ngram, _)| ngram.clone());
+        chars
+            .chain(ngrams)
+            .filter(|target| !words.iter().any(|w| w.contains(target.as_str())))
+            .collect()
+    }
+}
+
+// Words drawn from a list, favouring the ones that exercise the weak spots of the history.
+// The history is read again for every text, so each session shifts the next one.
+#[derive(Debug, Clone)]
+pub struct AdaptiveOptions {
+    pub list: String,
+    pub count: usize,
+    pub seed: u64,
+    pub history: History,
+}
+
+impl AdaptiveOptions {
+    pub fn describe(&self) -> String {
+        format!("adaptive:{}:{}:seed={}", self.list, self.count, self.seed)
+    }
+
+    pub fn generate(&self) -> Result<String, String> {
+        let sessions = self
+            .history
+            .load()
+            .map_err(|e| format!("Could not read history: {}", e))?;
+        self.generate_for(&Targets::from_history(&sessions))
+    }
+
+    pub fn generate_for(&self, targets: &Targets) -> Result<String, String> {
+        let list =
+            word_list(&self.list).ok_or_else(|| format!("Unknown word list {}", self.list))?;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let weights: Vec<f32> = list
+            .iter()
+            .map(|word| 1.0 + BOOST * targets.score(word))
+            .collect();
+        let index = WeightedIndex::new(&weights).map_err(|e| e.to_string())?;
+        let drills = targets.drills(&list);
+
+        let words = (0..self.count)
+            .map(|_| {
+                if !drills.is_empty() && rng.gen_bool(DRILL_RATE) {
+                    drills[rng.gen_range(0..drills.len())].clone()
+                } else {
+                    list[index.sample(&mut rng)].to_string()
+                }
+            })
+            .collect();
+
+        Ok(wrap(words))
+    }
+
+    pub fn reseeded(&self) -> AdaptiveOptions {
+        AdaptiveOptions {
+            seed: ChaCha8Rng::seed_from_u64(self.seed).gen(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AlignmentMode, CorrectionPolicy, NgramStats, TestMode};
+
+    fn session(keys_stats: Vec<(char, i32, i32)>, ngrams: Vec<(&str, u32, u128)>) -> SessionRecord {
+        SessionRecord {
+            source: String::new(),
+            mode: TestMode::Full,
+            alignment: AlignmentMode::Strict,
+            correction: CorrectionPolicy::Free,
+            started_at: 0,
+            ended_at: 0,
+            words_per_minute: None,
+            precision: None,
+            keys_precision: Vec::new(),
+            keys_stats,
+            actions: Vec::new(),
+            ngrams: ngrams
+                .into_iter()
+                .map(|(ngram, count, latency)| NgramStats {
+                    ngram: ngram.to_string(),
+                    count,
+                    errors: 0,
+                    latency: latency * count as u128,
+                    timed: count,
+                })
+                .collect(),
+        }
+    }
+
+    fn options() -> AdaptiveOptions {
+        AdaptiveOptions {
+            list: String::from("english_1k"),
+            count: 200,
+            seed: 7,
+            // No history yet, `load` fails without a path
+            history: History::new(Some(
+                std::env::temp_dir()
+                    .join(format!("blitzkey-no-history-{}.jsonl", std::process::id())),
+            )),
+        }
+    }
+
+    #[test]
+    fn finds_weak_spots() {
+        let sessions = vec![
+            session(
+                vec![('a', 10, 10), ('q', 5, 2), ('z', 4, 3), (' ', 10, 5)],
+                vec![("th", 4, 100), ("he", 4, 100), ("ck", 4, 400)],
+            ),
+            session(vec![('q', 5, 3)], vec![]),
+        ];
+
+        let targets = Targets::from_history(&sessions);
+        assert_eq!(targets.chars, vec![('q', 0.5), ('z', 0.25)]);
+        assert_eq!(targets.ngrams.len(), 1);
+        assert_eq!(targets.ngrams[0].0, "ck");
+        assert_eq!(Targets::from_history(&[]), Targets::default());
+    }
+
+    #[test]
+    fn favours_weak_spots() {
+        let count = |text: &str, c: char| text.matches(c).count();
+        let plain = options().generate_for(&Targets::default()).unwrap();
+        let targets = Targets {
+            chars: vec![('q', 1.0), ('7', 1.0)],
+            ngrams: vec![(String::from("ck"), 1.0)],
+        };
+        let adaptive = options().generate_for(&targets).unwrap();
+
+        assert_eq!(adaptive, options().generate_for(&targets).unwrap());
+        assert!(count(&adaptive, 'q') > 3 * count(&plain, 'q').max(1));
+        assert!(adaptive.matches("ck").count() > plain.matches("ck").count());
+        // No word has a digit, so it is drilled on its own
+        assert!(adaptive.split_whitespace().any(|w| w == "7"));
+        assert_eq!(
+            options().generate().unwrap().split_whitespace().count(),
+            200
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashMap, fmt, fs, io, path::PathBuf};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::state::Action;
+
+pub type Key = (KeyModifiers, KeyCode);
+
+// Editor-like defaults. The bindings file replaces the keys of the actions it lists, e.g.
+//   delete_word = ["ctrl+w", "alt+backspace"]
+//   pause = []
+const DEFAULT_BINDINGS: [(&str, &[&str]); 5] = [
+    (
+        "delete_word",
+        &["ctrl+backspace", "ctrl+w", "ctrl+h", "alt+backspace"],
+    ),
+    ("clear_line", &["ctrl+u"]),
+    ("restart", &["esc"]),
+    ("pause", &["ctrl+p"]),
+    ("quit", &["ctrl+c", "ctrl+q"]),
+];
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    UnknownAction(String),
+    InvalidKey(String),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io(e) => write!(f, "{}", e),
+            BindingsError::Toml(e) => write!(f, "{}", e),
+            BindingsError::UnknownAction(name) => write!(f, "unknown action {}", name),
+            BindingsError::InvalidKey(key) => write!(f, "invalid key {}", key),
+        }
+    }
+}
+
+impl From<io::Error> for BindingsError {
+    fn from(e: io::Error) -> Self {
+        BindingsError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for BindingsError {
+    fn from(e: toml::de::Error) -> Self {
+        BindingsError::Toml(e)
+    }
+}
+
+fn named_action(name: &str) -> Option<Action> {
+    match name {
+        "delete_word" => Some(Action::DeleteWord),
+        "clear_line" => Some(Action::ClearLine),
+        "restart" => Some(Action::Restart),
+        "pause" => Some(Action::Pause),
+        "quit" => Some(Action::Quit),
+        _ => None,
+    }
+}
+
+// Ctrl and Alt combinations are matched on the lowercase key whatever the shift state
+fn normalize((modifiers, code): Key) -> Key {
+    match code {
+        KeyCode::Char(c) if modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => (
+            modifiers - KeyModifiers::SHIFT,
+            KeyCode::Char(c.to_ascii_lowercase()),
+        ),
+        _ => (modifiers, code),
+    }
+}
+
+// Terminals reporting every key as an escape code may send the unshifted character along
+// with Shift, when they don't also send the shifted one
+fn shifted(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.len(), upper.next()) {
+        (1, Some(u)) => u,
+        _ => c,
+    }
+}
+
+// Keys are written as modifiers and a key name joined by '+', e.g. "ctrl+shift+w" or "esc"
+pub fn parse_key(s: &str) -> Result<Key, BindingsError> {
+    let invalid = || BindingsError::InvalidKey(s.to_string());
+    let lowercase = s.to_lowercase();
+    let mut parts: Vec<&str> = lowercase.split('+').collect();
+    let name = parts.pop().ok_or_else(invalid)?;
+
+    let mut modifiers = KeyModifiers::NONE;
+    for part in parts {
+        modifiers |= match part {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => return Err(invalid()),
+        };
+    }
+
+    let code = match name {
+        "backspace" => KeyCode::Backspace,
+        "esc" | "escape" => KeyCode::Esc,
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "delete" => KeyCode::Delete,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        f if f.len() > 1 && f.starts_with('f') => {
+            KeyCode::F(f[1..].parse().map_err(|_| invalid())?)
+        }
+        c if c.chars().count() == 1 => KeyCode::Char(c.chars().next().unwrap()),
+        _ => return Err(invalid()),
+    };
+
+    Ok(normalize((modifiers, code)))
+}
+
+pub struct Bindings {
+    keys: HashMap<Key, Action>,
+}
+
+impl Bindings {
+    fn from_names(names: HashMap<String, Vec<String>>) -> Result<Bindings, BindingsError> {
+        let mut keys = HashMap::new();
+        for (name, bound) in names {
+            let action =
+                named_action(&name).ok_or_else(|| BindingsError::UnknownAction(name.clone()))?;
+            for key in bound {
+                keys.insert(parse_key(&key)?, action.clone());
+            }
+        }
+        Ok(Bindings { keys })
+    }
+
+    fn default_names() -> HashMap<String, Vec<String>> {
+        DEFAULT_BINDINGS
+            .iter()
+            .map(|(name, keys)| {
+                (
+                    name.to_string(),
+                    keys.iter().map(|k| k.to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn defaults() -> Bindings {
+        Bindings::from_names(Bindings::default_names()).unwrap()
+    }
+
+    pub fn from_toml(text: &str) -> Result<Bindings, BindingsError> {
+        let mut names = Bindings::default_names();
+        names.extend(toml::from_str::<HashMap<String, Vec<String>>>(text)?);
+        Bindings::from_names(names)
+    }
+
+    // Defaults when there is no bindings file
+    pub fn load(path: Option<PathBuf>) -> Result<Bindings, BindingsError> {
+        match path.map(fs::read_to_string) {
+            Some(Ok(text)) => Bindings::from_toml(&text),
+            Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(Bindings::defaults()),
+        }
+    }
+
+    pub fn open_default() -> Result<Bindings, BindingsError> {
+        Bindings::load(dirs::config_dir().map(|d| d.join("blitzkey").join("bindings.toml")))
+    }
+
+    pub fn resolve(&self, key: KeyEvent) -> Option<Action> {
+        let (modifiers, code) = normalize((key.modifiers, key.code));
+        if let Some(action) = self.keys.get(&(modifiers, code)) {
+            return Some(action.clone());
+        }
+
+        // AltGr is reported as Ctrl+Alt on some platforms
+        let is_text = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            || modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
+        match key.code {
+            KeyCode::Char(c) if is_text && modifiers.contains(KeyModifiers::SHIFT) => {
+                Some(Action::Char(shifted(c)))
+            }
+            KeyCode::Char(c) if is_text => Some(Action::Char(c)),
+            KeyCode::Backspace => Some(Action::Backspace),
+            KeyCode::Enter => Some(Action::Char('\n')),
+            KeyCode::Tab => Some(Action::Char('\t')),
+            KeyCode::Up => Some(Action::Up),
+            KeyCode::Down => Some(Action::Down),
+            KeyCode::Left => Some(Action::Left),
+            KeyCode::Right => Some(Action::Right),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(modifiers: KeyModifiers, code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn defaults_match_editors() {
+        let bindings = Bindings::defaults();
+        let ctrl = KeyModifiers::CONTROL;
+
+        assert!(matches!(
+            bindings.resolve(press(ctrl, KeyCode::Char('w'))),
+            Some(Action::DeleteWord)
+        ));
+        assert!(matches!(
+            bindings.resolve(press(ctrl | KeyModifiers::SHIFT, KeyCode::Char('W'))),
+            Some(Action::DeleteWord)
+        ));
+        assert!(matches!(
+            bindings.resolve(press(KeyModifiers::NONE, KeyCode::Esc)),
+            Some(Action::Restart)
+        ));
+        assert!(matches!(
+            bindings.resolve(press(KeyModifiers::SHIFT, KeyCode::Char('W'))),
+            Some(Action::Char('W'))
+        ));
+        assert!(matches!(
+            bindings.resolve(press(KeyModifiers::SHIFT, KeyCode::Char('w'))),
+            Some(Action::Char('W'))
+        ));
+        // Unbound control keys are not typed
+        assert!(bindings.resolve(press(ctrl, KeyCode::Char('x'))).is_none());
+    }
+
+    #[test]
+    fn file_replaces_listed_actions() {
+        let bindings = Bindings::from_toml("restart = [\"ctrl+r\"]\npause = []").unwrap();
+
+        assert!(matches!(
+            bindings.resolve(press(KeyModifiers::CONTROL, KeyCode::Char('r'))),
+            Some(Action::Restart)
+        ));
+        assert!(bindings
+            .resolve(press(KeyModifiers::NONE, KeyCode::Esc))
+            .is_none());
+        assert!(bindings
+            .resolve(press(KeyModifiers::CONTROL, KeyCode::Char('p')))
+            .is_none());
+        assert!(matches!(
+            bindings.resolve(press(KeyModifiers::CONTROL, KeyCode::Char('c'))),
+            Some(Action::Quit)
+        ));
+
+        assert!(matches!(
+            Bindings::from_toml("explode = [\"x\"]"),
+            Err(BindingsError::UnknownAction(_))
+        ));
+        assert!(matches!(
+            Bindings::from_toml("quit = [\"hyper+x\"]"),
+            Err(BindingsError::InvalidKey(_))
+        ));
+    }
+}
@@ -1,32 +1,490 @@
-use clap::{arg, command, Command};
+use chrono::NaiveDate;
+use std::path::Path;
 
-use crate::utils::read_file_to_string;
+use clap::{arg, command, ArgAction, ArgMatches, Command};
+
+use crate::adaptive::AdaptiveOptions;
+use crate::curriculum::{CurriculumOptions, Goals, ProgressStore};
+use crate::heatmap::{Heatmap, HeatmapMetric, HeatmapScope};
+use crate::history::History;
+use crate::keyboard::{Guide, LayoutSource, DEFAULT_LAYOUT, GUIDES, LAYOUTS};
+use crate::playlist::Playlist;
+use crate::report::{Format, Period, StatsQuery};
+use crate::source::TextSource;
+use crate::state::{
+    AlignmentMode, CorrectionPolicy, TestMode, CORRECTION_POLICIES, TIMED_DURATIONS,
+};
+use crate::utils::canonical_source;
+use crate::words::{words_for_duration, WordsOptions, DEFAULT_WORD_LIST, WORD_LISTS};
 
 // --------------------------------
 
-pub fn main() -> Option<String> {
+pub struct Practice {
+    // Opens the home menu when there is none
+    pub source: Option<TextSource>,
+    pub mode: TestMode,
+    pub alignment: AlignmentMode,
+    pub correction: CorrectionPolicy,
+    pub layout: LayoutSource,
+    // Name of the built-in layout emulated on the physical one
+    pub emulate: Option<String>,
+    pub guide: Guide,
+    pub heatmap: Option<Heatmap>,
+}
+
+pub enum LayoutCommand {
+    // Prints a layout file in the TOML format
+    Convert(LayoutSource),
+    // Reports the problems of a layout, and the characters of the text it can't type
+    Check {
+        layout: LayoutSource,
+        text: Option<String>,
+    },
+}
+
+pub enum CliCommand {
+    Practice(Practice),
+    Stats(StatsQuery),
+    Layout(LayoutCommand),
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|e| format!("expected YYYY-MM-DD: {}", e))
+}
+
+fn parse_duration(s: &str) -> Result<u64, String> {
+    match s.parse() {
+        Ok(seconds) if TIMED_DURATIONS.contains(&seconds) => Ok(seconds),
+        _ => Err(format!("expected one of {:?}", TIMED_DURATIONS)),
+    }
+}
+
+fn timed_mode(matches: &ArgMatches) -> TestMode {
+    matches
+        .get_one::<u64>("time")
+        .map_or(TestMode::Full, |t| TestMode::Timed(*t))
+}
+
+fn alignment_mode(matches: &ArgMatches) -> AlignmentMode {
+    match matches.get_one::<String>("alignment").map(String::as_str) {
+        Some("edit-distance") => AlignmentMode::EditDistance,
+        _ => AlignmentMode::Strict,
+    }
+}
+
+fn alignment_arg() -> clap::Arg {
+    arg!(--alignment <MODE> "How typed characters are matched with the text")
+        .value_parser(["strict", "edit-distance"])
+        .default_value("strict")
+}
+
+fn correction_policy(matches: &ArgMatches) -> CorrectionPolicy {
+    let name = matches.get_one::<String>("correction").unwrap();
+    CORRECTION_POLICIES
+        .into_iter()
+        .find(|p| p.name() == name)
+        .unwrap_or_default()
+}
+
+fn correction_arg() -> clap::Arg {
+    arg!(--correction <POLICY> "What can be done about mistakes")
+        .value_parser(CORRECTION_POLICIES.map(|p| p.name()))
+        .default_value(CorrectionPolicy::Free.name())
+}
+
+fn layout_source(matches: &ArgMatches) -> LayoutSource {
+    match matches.get_one::<String>("layout-file") {
+        Some(path) => LayoutSource::File(path.clone()),
+        None => LayoutSource::Builtin(matches.get_one::<String>("layout").unwrap().clone()),
+    }
+}
+
+fn guide(matches: &ArgMatches) -> Guide {
+    let name = matches.get_one::<String>("guide").unwrap();
+    GUIDES
+        .into_iter()
+        .find(|(n, _)| n == name)
+        .map_or(Guide::Off, |(_, g)| g)
+}
+
+fn heatmap(matches: &ArgMatches) -> Option<Heatmap> {
+    let metric = match matches.get_one::<String>("heatmap")?.as_str() {
+        "speed" => HeatmapMetric::Speed,
+        _ => HeatmapMetric::Accuracy,
+    };
+    let scope = match matches
+        .get_one::<String>("heatmap-scope")
+        .map(String::as_str)
+    {
+        Some("history") => HeatmapScope::History,
+        _ => HeatmapScope::Session,
+    };
+    Some(Heatmap::new(metric, scope))
+}
+
+fn heatmap_args() -> [clap::Arg; 2] {
+    [
+        arg!(--heatmap <METRIC> "Tints the keys by accuracy or by speed")
+            .value_parser(["accuracy", "speed"]),
+        arg!(--"heatmap-scope" <SCOPE> "Sessions the heatmap is computed from")
+            .value_parser(["session", "history"])
+            .default_value("session")
+            .requires("heatmap"),
+    ]
+}
+
+fn layout_args() -> [clap::Arg; 4] {
+    [
+        arg!(--layout <NAME> "Keyboard layout shown under the text")
+            .value_parser(LAYOUTS.map(|(name, _)| name))
+            .default_value(DEFAULT_LAYOUT),
+        arg!(--"layout-file" <PATH> "Loads the keyboard layout from a file instead")
+            .conflicts_with("layout"),
+        arg!(--emulate <NAME> "Remaps the keys of the physical layout to practice this one")
+            .value_parser(LAYOUTS.map(|(name, _)| name)),
+        arg!(--guide <MODE> "Highlights the next key to press, and with fingers who presses it")
+            .value_parser(GUIDES.map(|(name, _)| name))
+            .default_value("off"),
+    ]
+}
+
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+// The words of the timed tests are enough for their duration
+fn word_count(matches: &ArgMatches, mode: TestMode) -> usize {
+    match mode {
+        TestMode::Timed(seconds) => words_for_duration(seconds),
+        TestMode::Full => *matches.get_one::<u64>("count").unwrap() as usize,
+    }
+}
+
+fn seed(matches: &ArgMatches) -> u64 {
+    matches
+        .get_one::<u64>("seed")
+        .copied()
+        .unwrap_or_else(random_seed)
+}
+
+fn words_practice(matches: &ArgMatches) -> Practice {
+    let source = TextSource::Words(WordsOptions {
+        list: matches.get_one::<String>("list").unwrap().clone(),
+        count: word_count(matches, timed_mode(matches)),
+        punctuation: matches.get_flag("punctuation"),
+        numbers: matches.get_flag("numbers"),
+        capitalize: matches.get_flag("capitalize"),
+        seed: seed(matches),
+    });
+    practice(matches, source)
+}
+
+fn adaptive_practice(matches: &ArgMatches) -> Practice {
+    let source = TextSource::Adaptive(AdaptiveOptions {
+        list: matches.get_one::<String>("list").unwrap().clone(),
+        count: word_count(matches, timed_mode(matches)),
+        seed: seed(matches),
+        history: History::open_default(),
+    });
+    practice(matches, source)
+}
+
+// The letters are ordered once the keyboard is loaded
+fn learn_practice(matches: &ArgMatches) -> Practice {
+    let source = TextSource::Curriculum(CurriculumOptions {
+        count: word_count(matches, timed_mode(matches)),
+        seed: seed(matches),
+        layout: String::new(),
+        order: Vec::new(),
+        goals: Goals {
+            words_per_minute: *matches.get_one::<u64>("target-wpm").unwrap() as f32,
+            accuracy: *matches.get_one::<u64>("target-accuracy").unwrap() as f32 / 100.0,
+        },
+        store: ProgressStore::open_default(),
+    });
+    practice(matches, source)
+}
+
+fn stats_query(matches: &ArgMatches) -> StatsQuery {
+    let format = if matches.get_flag("json") {
+        Format::Json
+    } else if matches.get_flag("csv") {
+        Format::Csv
+    } else {
+        Format::Table
+    };
+
+    let period = match matches.get_one::<String>("period").map(String::as_str) {
+        Some("weekly") => Period::Weekly,
+        _ => Period::Daily,
+    };
+
+    StatsQuery {
+        from: matches.get_one::<NaiveDate>("from").copied(),
+        to: matches.get_one::<NaiveDate>("to").copied(),
+        source: matches.get_one::<String>("source").cloned(),
+        mode: matches.get_one::<String>("mode").cloned(),
+        correction: matches.get_one::<String>("correction").cloned(),
+        period,
+        format,
+        keys: matches.get_flag("keys"),
+        ngrams: matches.get_flag("ngrams"),
+    }
+}
+
+fn practice(matches: &ArgMatches, source: TextSource) -> Practice {
+    Practice {
+        source: Some(source),
+        mode: timed_mode(matches),
+        alignment: alignment_mode(matches),
+        correction: correction_policy(matches),
+        layout: layout_source(matches),
+        emulate: matches.get_one::<String>("emulate").cloned(),
+        guide: guide(matches),
+        heatmap: heatmap(matches),
+    }
+}
+
+fn home_command() -> Command {
+    Command::new("home")
+        .about("Opens the home menu, also shown without a command")
+        .arg(alignment_arg())
+        .arg(correction_arg())
+        .args(layout_args())
+        .args(heatmap_args())
+}
+
+fn home_practice(matches: &ArgMatches) -> Practice {
+    Practice {
+        source: None,
+        mode: TestMode::Full,
+        alignment: alignment_mode(matches),
+        correction: correction_policy(matches),
+        layout: layout_source(matches),
+        emulate: matches.get_one::<String>("emulate").cloned(),
+        guide: guide(matches),
+        heatmap: heatmap(matches),
+    }
+}
+
+pub fn main() -> Option<CliCommand> {
     let matches = command!() // requires `cargo` feature
         .propagate_version(true)
-        .subcommand_required(true)
-        .arg_required_else_help(true)
+        .subcommand(home_command())
+        .subcommand(
+            Command::new("from-file")
+                .about("Practices files, one after the other, or every file of directories")
+                .arg(
+                    arg!([PATH]...)
+                        .help("The files or directories to practice, - to read the text from stdin")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    arg!(--glob <PATTERN> "Only practices the files of directories matching it, ! to exclude")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    arg!(--time <SECONDS> "Ends the test after this many seconds")
+                        .value_parser(parse_duration),
+                )
+                .arg(alignment_arg())
+                .arg(correction_arg())
+                .args(layout_args())
+                .args(heatmap_args()),
+        )
+        .subcommand(
+            Command::new("from-stdin")
+                .about("Practices the text piped in, the keys are read from the terminal")
+                .arg(
+                    arg!(--time <SECONDS> "Ends the test after this many seconds")
+                        .value_parser(parse_duration),
+                )
+                .arg(alignment_arg())
+                .arg(correction_arg())
+                .args(layout_args())
+                .args(heatmap_args()),
+        )
+        .subcommand(
+            Command::new("words")
+                .about("Practices random words from a built-in frequency list")
+                .arg(
+                    arg!(--list <NAME> "Word list to draw from")
+                        .value_parser(WORD_LISTS.map(|(name, _)| name))
+                        .default_value(DEFAULT_WORD_LIST),
+                )
+                .arg(
+                    arg!(--count <N> "Number of words")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("50")
+                        .conflicts_with("time"),
+                )
+                .arg(
+                    arg!(--time <SECONDS> "Ends the test after this many seconds")
+                        .value_parser(parse_duration),
+                )
+                .arg(arg!(--punctuation "Adds punctuation and sentence capitals"))
+                .arg(arg!(--numbers "Mixes numbers in with the words"))
+                .arg(arg!(--capitalize "Capitalizes some of the words"))
+                .arg(
+                    arg!(--seed <SEED> "Seed of the generator, to replay the exact same text")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(alignment_arg())
+                .arg(correction_arg())
+                .args(layout_args())
+                .args(heatmap_args()),
+        )
+        .subcommand(
+            Command::new("adaptive")
+                .about("Practices words that exercise the weakest keys and n-grams of the history")
+                .arg(
+                    arg!(--list <NAME> "Word list to draw from")
+                        .value_parser(WORD_LISTS.map(|(name, _)| name))
+                        .default_value(DEFAULT_WORD_LIST),
+                )
+                .arg(
+                    arg!(--count <N> "Number of words")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("50")
+                        .conflicts_with("time"),
+                )
+                .arg(
+                    arg!(--time <SECONDS> "Ends the test after this many seconds")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    arg!(--seed <SEED> "Seed of the generator, to replay the exact same text")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(alignment_arg())
+                .arg(correction_arg())
+                .args(layout_args())
+                .args(heatmap_args()),
+        )
+        .subcommand(
+            Command::new("learn")
+                .about("Learns the keys a few at a time, unlocking the next one once the others are mastered")
+                .arg(
+                    arg!(--count <N> "Number of words")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("30")
+                        .conflicts_with("time"),
+                )
+                .arg(
+                    arg!(--time <SECONDS> "Ends the test after this many seconds")
+                        .value_parser(parse_duration),
+                )
+                .arg(
+                    arg!(--seed <SEED> "Seed of the generator, to replay the exact same text")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    arg!(--"target-wpm" <WPM> "Speed each key must reach before the next is unlocked")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("35"),
+                )
+                .arg(
+                    arg!(--"target-accuracy" <PERCENT> "Accuracy each key must reach before the next is unlocked")
+                        .value_parser(clap::value_parser!(u64).range(1..=100))
+                        .default_value("95"),
+                )
+                .arg(alignment_arg())
+                .arg(correction_arg())
+                .args(layout_args())
+                .args(heatmap_args()),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Shows WPM and accuracy trends from the saved sessions")
+                .arg(
+                    arg!(--from <DATE> "Only sessions on or after this day (YYYY-MM-DD)")
+                        .value_parser(parse_date),
+                )
+                .arg(
+                    arg!(--to <DATE> "Only sessions on or before this day (YYYY-MM-DD)")
+                        .value_parser(parse_date),
+                )
+                .arg(arg!(--source <PATH> "Only sessions whose source contains PATH"))
+                .arg(
+                    arg!(--mode <MODE> "Only sessions in this mode (full, timed or e.g. timed-30)"),
+                )
+                .arg(
+                    arg!(--correction <POLICY> "Only sessions with this correction policy")
+                        .value_parser(CORRECTION_POLICIES.map(|p| p.name())),
+                )
+                .arg(
+                    arg!(--period <PERIOD> "Aggregation period")
+                        .value_parser(["daily", "weekly"])
+                        .default_value("daily"),
+                )
+                .arg(arg!(--keys "Shows per-key accuracy trends instead of periods"))
+                .arg(
+                    arg!(--ngrams "Shows the slowest and most error-prone bigrams and trigrams")
+                        .conflicts_with("keys"),
+                )
+                .arg(arg!(--json "Prints the report as JSON").conflicts_with("csv"))
+                .arg(arg!(--csv "Prints the report as CSV")),
+        )
         .subcommand(
-            Command::new("from-file").about("Adds files to myapp").arg(
-                arg!([PATH])
-                    .help("The name of the file to add")
-                    .required(true)
-                    .index(1),
-            ),
+            Command::new("layout")
+                .about("Works with keyboard layout files")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("convert")
+                        .about("Prints a layout drawing and mask file in the TOML format")
+                        .arg(arg!(<PATH> "The layout file to convert")),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Reports the problems of a layout file")
+                        .arg(arg!(<PATH> "The layout file to check"))
+                        .arg(arg!(--text <FILE> "Also reports the characters of this text no key can type")),
+                ),
         )
         .get_matches();
 
     match matches.subcommand() {
         Some(("from-file", sub_matches)) => {
-            let path = sub_matches.get_one::<String>("PATH")?;
-            println!("'myapp add' was used, name is: {:?}", path);
-            read_file_to_string(path).ok()
+            let paths: Vec<String> = sub_matches.get_many::<String>("PATH")?.cloned().collect();
+            let globs: Vec<String> = sub_matches
+                .get_many::<String>("glob")
+                .map_or(Vec::new(), |globs| globs.cloned().collect());
+            let source = match paths.as_slice() {
+                [path] if path == "-" => TextSource::Stdin,
+                [path] if globs.is_empty() && !Path::new(path).is_dir() => {
+                    TextSource::File(canonical_source(path))
+                }
+                _ => TextSource::Playlist(Playlist::new(paths, globs)),
+            };
+            Some(CliCommand::Practice(practice(sub_matches, source)))
         }
-        _ => {
-            unreachable!("Exhausted list of subcommands and subcommand_required prevents `None`")
+        Some(("from-stdin", sub_matches)) => Some(CliCommand::Practice(practice(
+            sub_matches,
+            TextSource::Stdin,
+        ))),
+        Some(("home", sub_matches)) => Some(CliCommand::Practice(home_practice(sub_matches))),
+        Some(("words", sub_matches)) => Some(CliCommand::Practice(words_practice(sub_matches))),
+        Some(("learn", sub_matches)) => Some(CliCommand::Practice(learn_practice(sub_matches))),
+        Some(("adaptive", sub_matches)) => {
+            Some(CliCommand::Practice(adaptive_practice(sub_matches)))
         }
+        Some(("stats", sub_matches)) => Some(CliCommand::Stats(stats_query(sub_matches))),
+        Some(("layout", sub_matches)) => match sub_matches.subcommand() {
+            Some(("convert", convert_matches)) => Some(CliCommand::Layout(LayoutCommand::Convert(
+                LayoutSource::File(convert_matches.get_one::<String>("PATH")?.clone()),
+            ))),
+            Some(("check", check_matches)) => Some(CliCommand::Layout(LayoutCommand::Check {
+                layout: LayoutSource::File(check_matches.get_one::<String>("PATH")?.clone()),
+                text: check_matches.get_one::<String>("text").cloned(),
+            })),
+            _ => unreachable!("layout requires a subcommand"),
+        },
+        Some(_) => unreachable!("Exhausted list of subcommands"),
+        None => Some(CliCommand::Practice(home_practice(
+            &home_command().get_matches_from(["home"]),
+        ))),
     }
 }
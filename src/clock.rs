@@ -0,0 +1,65 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// Source of the millisecond timestamps used for every action and statistic.
+pub trait Clock {
+    // Milliseconds since the clock started, never going backwards
+    fn now(&self) -> u128;
+
+    // Converts a timestamp of this clock to a Unix timestamp in milliseconds
+    fn to_unix_millis(&self, time: u128) -> u64;
+}
+
+pub struct MonotonicClock {
+    origin: Instant,
+    unix_origin: u128,
+}
+
+impl MonotonicClock {
+    pub fn new() -> MonotonicClock {
+        MonotonicClock {
+            origin: Instant::now(),
+            unix_origin: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis()),
+        }
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> u128 {
+        self.origin.elapsed().as_millis()
+    }
+
+    fn to_unix_millis(&self, time: u128) -> u64 {
+        (self.unix_origin + time) as u64
+    }
+}
+
+#[cfg(test)]
+pub struct ManualClock {
+    time: std::cell::Cell<u128>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock {
+            time: std::cell::Cell::new(0),
+        }
+    }
+
+    pub fn set(&self, time: u128) {
+        self.time.set(time);
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> u128 {
+        self.time.get()
+    }
+
+    fn to_unix_millis(&self, time: u128) -> u64 {
+        time as u64
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::history::HistoryError;
+use crate::keyboard::{Keyboard, DEFAULT_LAYOUT};
+use crate::layout::{Finger, Hand};
+use crate::state::{Keystroke, IDLE_LATENCY};
+use crate::words::{word_list, wrap};
+
+// Letters unlocked before any practice
+pub const START_KEYS: usize = 7;
+
+// Latest keystrokes kept for each letter, and how many are needed to judge it
+const WINDOW: usize = 30;
+const MIN_SAMPLES: usize = 15;
+
+// Share of the words made to contain the letter that needs the most work
+const FOCUS_RATE: f64 = 0.5;
+
+// Word list the pseudo-words imitate
+const MODEL_LIST: &str = "english_10k";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Goals {
+    pub words_per_minute: f32,
+    // Between 0 and 1
+    pub accuracy: f32,
+}
+
+pub const DEFAULT_GOALS: Goals = Goals {
+    words_per_minute: 35.0,
+    accuracy: 0.95,
+};
+
+// Words of a lesson outside of timed tests
+pub const LESSON_WORDS: usize = 30;
+
+impl Goals {
+    // Mean time between keystrokes at the target speed, a word being 5 characters
+    pub fn latency(&self) -> f32 {
+        60000.0 / (self.words_per_minute * 5.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub correct: bool,
+    // Time since the previous keystroke, unless it followed a pause or a correction
+    pub latency: Option<u128>,
+}
+
+// Letters are unlocked in order: the home row, then the top and bottom rows, strongest
+// fingers first, and the keys an index finger stretches to after the others of the row.
+pub fn letter_order(keyboard: &Keyboard) -> Vec<char> {
+    let letters: Vec<(char, usize, Finger, f32)> = keyboard
+        .keys
+        .iter()
+        .filter_map(|key| {
+            let c = key.base.filter(|c| c.is_alphabetic())?;
+            Some((c, key.row, key.finger?, key.col + key.width / 2.0))
+        })
+        .collect();
+    let row_rank = |row: usize| match row {
+        2 => 0,
+        1 => 1,
+        3 => 2,
+        0 => 3,
+        _ => 4,
+    };
+    let finger_rank = |finger: Finger| match finger {
+        Finger::LeftIndex | Finger::RightIndex => 0,
+        Finger::LeftMiddle | Finger::RightMiddle => 1,
+        Finger::LeftRing | Finger::RightRing => 2,
+        Finger::LeftPinky | Finger::RightPinky => 3,
+        Finger::LeftThumb | Finger::RightThumb => 4,
+    };
+    // A finger rests on its outermost key of the row and stretches inwards to the others
+    let stretch = |(_, row, finger, col): &(char, usize, Finger, f32)| {
+        letters.iter().any(|(_, r, f, c)| {
+            r == row
+                && f == finger
+                && match finger.hand() {
+                    Hand::Left => c < col,
+                    Hand::Right => c > col,
+                }
+        })
+    };
+
+    let mut ranked: Vec<_> = letters
+        .iter()
+        .map(|letter| {
+            let (c, row, finger, _) = *letter;
+            (
+                (
+                    row_rank(row),
+                    stretch(letter),
+                    finger_rank(finger),
+                    finger.hand() == Hand::Right,
+                ),
+                c,
+            )
+        })
+        .collect();
+    ranked.sort_by_key(|(rank, _)| *rank);
+    ranked.into_iter().map(|(_, c)| c).collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub unlocked: Vec<char>,
+    // Latest keystrokes of each letter, oldest first
+    pub samples: HashMap<char, Vec<Sample>>,
+}
+
+impl Progress {
+    // Accuracy and mean latency over the window, once it has enough keystrokes
+    pub fn key_stats(&self, c: char) -> Option<(f32, Option<f32>)> {
+        let samples = self.samples.get(&c).filter(|s| s.len() >= MIN_SAMPLES)?;
+        let correct = samples.iter().filter(|s| s.correct).count();
+        let latencies: Vec<u128> = samples.iter().filter_map(|s| s.latency).collect();
+        let latency = (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<u128>() as f32 / latencies.len() as f32);
+        Some((correct as f32 / samples.len() as f32, latency))
+    }
+
+    pub fn meets(&self, c: char, goals: &Goals) -> bool {
+        match self.key_stats(c) {
+            Some((accuracy, Some(latency))) => {
+                accuracy >= goals.accuracy && latency <= goals.latency()
+            }
+            _ => false,
+        }
+    }
+
+    // The most recently unlocked letter still short of the goals
+    pub fn focus(&self, goals: &Goals) -> Option<char> {
+        self.unlocked
+            .iter()
+            .rev()
+            .find(|c| !self.meets(**c, goals))
+            .copied()
+    }
+
+    pub fn next_key(&self, order: &[char]) -> Option<char> {
+        order.iter().find(|c| !self.unlocked.contains(c)).copied()
+    }
+
+    fn start(&mut self, order: &[char]) {
+        for c in order {
+            if self.unlocked.len() >= START_KEYS {
+                break;
+            }
+            if !self.unlocked.contains(c) {
+                self.unlocked.push(*c);
+            }
+        }
+    }
+
+    // Adds the letters of a session to the windows, and returns the letter unlocked when
+    // all the others meet the goals
+    pub fn record(
+        &mut self,
+        order: &[char],
+        keystrokes: &[Keystroke],
+        goals: &Goals,
+    ) -> Option<char> {
+        self.start(order);
+
+        let mut previous: Option<&Keystroke> = None;
+        for keystroke in keystrokes {
+            let latency = previous
+                .filter(|p| p.run == keystroke.run)
+                .map(|p| keystroke.time.saturating_sub(p.time))
+                .filter(|latency| *latency <= IDLE_LATENCY);
+            previous = Some(keystroke);
+
+            if !order.contains(&keystroke.expected) {
+                continue;
+            }
+            let samples = self.samples.entry(keystroke.expected).or_default();
+            samples.push(Sample {
+                correct: keystroke.typed == keystroke.expected,
+                latency,
+            });
+            if samples.len() > WINDOW {
+                samples.remove(0);
+            }
+        }
+
+        if !self.unlocked.iter().all(|c| self.meets(*c, goals)) {
+            return None;
+        }
+        let next = self.next_key(order)?;
+        self.unlocked.push(next);
+        Some(next)
+    }
+}
+
+// JSON file holding the progress through the curriculum of each layout, since the letters
+// are unlocked in a different order on each of them
+#[derive(Debug, Clone)]
+pub struct ProgressStore {
+    path: Option<PathBuf>,
+}
+
+impl ProgressStore {
+    pub fn new(path: Option<PathBuf>) -> ProgressStore {
+        ProgressStore { path }
+    }
+
+    pub fn open_default() -> ProgressStore {
+        ProgressStore::new(dirs::data_dir().map(|d| d.join("blitzkey").join("curriculum.json")))
+    }
+
+    fn load_all(&self) -> Result<BTreeMap<String, Progress>, HistoryError> {
+        let path = self.path.as_ref().ok_or(HistoryError::NoDataDir)?;
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        match serde_json::from_str(&json) {
+            Ok(layouts) => Ok(layouts),
+            // Files written before the progress was kept per layout
+            Err(e) => match serde_json::from_str::<Progress>(&json) {
+                Ok(progress) => Ok(BTreeMap::from([(DEFAULT_LAYOUT.to_string(), progress)])),
+                Err(_) => Err(e.into()),
+            },
+        }
+    }
+
+    pub fn load(&self, layout: &str) -> Result<Progress, HistoryError> {
+        Ok(self.load_all()?.remove(layout).unwrap_or_default())
+    }
+
+    pub fn save(&self, layout: &str, progress: &Progress) -> Result<(), HistoryError> {
+        let mut layouts = self.load_all()?;
+        layouts.insert(layout.to_string(), progress.clone());
+
+        let path = self.path.as_ref().ok_or(HistoryError::NoDataDir)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = fs::File::create(path)?;
+        file.write_all(serde_json::to_string(&layouts)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+// How often each letter follows another in the words of a list, '^' starting a word
+fn transitions(words: &[&str]) -> HashMap<(char, char), u32> {
+    let mut counts = HashMap::new();
+    for word in words {
+        let mut previous = '^';
+        for c in word.chars() {
+            *counts.entry((previous, c)).or_insert(0) += 1;
+            previous = c;
+        }
+    }
+    counts
+}
+
+// Words of 3 to 7 letters drawn from the unlocked ones, following the letter pairs of real
+// words. Some of them are made to contain the focus letter.
+fn pseudo_words(letters: &[char], focus: Option<char>, count: usize, seed: u64) -> Vec<String> {
+    let model = transitions(&word_list(MODEL_LIST).unwrap_or_default());
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+    let word = |rng: &mut ChaCha8Rng| {
+        let len = rng.gen_range(3..=7);
+        let mut word = String::new();
+        let mut previous = '^';
+        for _ in 0..len {
+            let weights: Vec<u32> = letters
+                .iter()
+                .map(|c| model.get(&(previous, *c)).copied().unwrap_or(0) + 1)
+                .collect();
+            let index = WeightedIndex::new(&weights).unwrap();
+            previous = letters[index.sample(rng)];
+            word.push(previous);
+        }
+        word
+    };
+
+    (0..count)
+        .map(|_| {
+            let wants_focus = focus.filter(|_| rng.gen_bool(FOCUS_RATE));
+            let mut candidate = word(&mut rng);
+            for _ in 0..20 {
+                if wants_focus.is_none_or(|c| candidate.contains(c)) {
+                    break;
+                }
+                candidate = word(&mut rng);
+            }
+            candidate
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct CurriculumOptions {
+    pub count: usize,
+    pub seed: u64,
+    // Name of the layout and its letters in the order they are unlocked
+    pub layout: String,
+    pub order: Vec<char>,
+    pub goals: Goals,
+    pub store: ProgressStore,
+}
+
+impl CurriculumOptions {
+    pub fn describe(&self) -> String {
+        format!("learn:{}:seed={}", self.count, self.seed)
+    }
+
+    pub fn progress(&self) -> Result<Progress, String> {
+        let mut progress = self
+            .store
+            .load(&self.layout)
+            .map_err(|e| format!("Could not read the curriculum progress: {}", e))?;
+        progress.start(&self.order);
+        Ok(progress)
+    }
+
+    pub fn generate(&self) -> Result<String, String> {
+        let progress = self.progress()?;
+        if progress.unlocked.is_empty() {
+            return Err(String::from("The keyboard layout has no letters to learn"));
+        }
+        let words = pseudo_words(
+            &progress.unlocked,
+            progress.focus(&self.goals),
+            self.count,
+            self.seed,
+        );
+        Ok(wrap(words))
+    }
+
+    // Saves the keystrokes of a finished session
+    pub fn record(&self, keystrokes: &[Keystroke]) -> Result<Option<char>, HistoryError> {
+        let mut progress = self.store.load(&self.layout)?;
+        let unlocked = progress.record(&self.order, keystrokes, &self.goals);
+        self.store.save(&self.layout, &progress)?;
+        Ok(unlocked)
+    }
+
+    // Keys of the letters not unlocked yet
+    pub fn locked_keys(&self, keyboard: &Keyboard) -> HashSet<usize> {
+        let Ok(progress) = self.progress() else {
+            return HashSet::new();
+        };
+        self.order
+            .iter()
+            .filter(|c| !progress.unlocked.contains(c))
+            .filter_map(|c| keyboard.key_for(*c))
+            .collect()
+    }
+
+    pub fn reseeded(&self) -> CurriculumOptions {
+        CurriculumOptions {
+            seed: ChaCha8Rng::seed_from_u64(self.seed).gen(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::LayoutSource;
+
+    const GOALS: Goals = Goals {
+        words_per_minute: 30.0,
+        accuracy: 0.9,
+    };
+
+    fn keyboard(name: &str) -> Keyboard {
+        LayoutSource::Builtin(String::from(name)).load().unwrap()
+    }
+
+    // Each letter typed `times` times, `interval` ms apart
+    fn typed(letters: &str, times: usize, interval: u128) -> Vec<Keystroke> {
+        letters
+            .chars()
+            .cycle()
+            .take(letters.len() * times)
+            .enumerate()
+            .map(|(i, c)| Keystroke {
+                expected: c,
+                typed: c,
+                time: i as u128 * interval,
+                run: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_letters_from_the_home_row() {
+        let qwerty: String = letter_order(&keyboard("qwerty")).into_iter().collect();
+        assert_eq!(qwerty, "fjdkslaghrueiwoqptyvmcxzbn");
+        let colemak: String = letter_order(&keyboard("colemak")).into_iter().collect();
+        assert!(colemak.starts_with("tnseria"));
+    }
+
+    #[test]
+    fn unlocks_keys_when_the_goals_are_met() {
+        let order = letter_order(&keyboard("qwerty"));
+        let mut progress = Progress::default();
+
+        // Too slow for the goals
+        assert_eq!(
+            progress.record(&order, &typed("fjdksla", 20, 500), &GOALS),
+            None
+        );
+        assert_eq!(progress.unlocked.len(), START_KEYS);
+        assert_eq!(progress.focus(&GOALS), Some('a'));
+
+        assert_eq!(
+            progress.record(&order, &typed("fjdksla", 30, 100), &GOALS),
+            Some('g')
+        );
+        assert_eq!(progress.samples[&'f'].len(), WINDOW);
+        assert_eq!(progress.focus(&GOALS), Some('g'));
+        assert_eq!(progress.next_key(&order), Some('h'));
+    }
+
+    #[test]
+    fn generates_words_from_unlocked_letters() {
+        let store = ProgressStore::new(Some(
+            std::env::temp_dir().join(format!("blitzkey-curriculum-{}.json", std::process::id())),
+        ));
+        let keyboard = keyboard("qwerty");
+        let options = CurriculumOptions {
+            count: 40,
+            seed: 3,
+            layout: keyboard.name.clone(),
+            order: letter_order(&keyboard),
+            goals: GOALS,
+            store: store.clone(),
+        };
+
+        let text = options.generate().unwrap();
+        assert_eq!(text.split_whitespace().count(), 40);
+        assert!(text
+            .chars()
+            .all(|c| c.is_whitespace() || "fjdksla".contains(c)));
+        assert_eq!(options.locked_keys(&keyboard).len(), 26 - START_KEYS);
+
+        assert_eq!(
+            options.record(&typed("fjdksla", 30, 100)).unwrap(),
+            Some('g')
+        );
+        assert!(options.reseeded().generate().unwrap().contains('g'));
+        assert_eq!(store.load("qwerty").unwrap().unlocked.len(), START_KEYS + 1);
+
+        // Another layout starts over
+        let dvorak = LayoutSource::Builtin(String::from("dvorak"))
+            .load()
+            .unwrap();
+        let options = CurriculumOptions {
+            layout: dvorak.name.clone(),
+            order: letter_order(&dvorak),
+            ..options
+        };
+        assert_eq!(options.progress().unwrap().unlocked.len(), START_KEYS);
+        assert_eq!(options.locked_keys(&dvorak).len(), 26 - START_KEYS);
+        options.record(&typed("aoe", 1, 100)).unwrap();
+        assert_eq!(store.load("dvorak").unwrap().unlocked.len(), START_KEYS);
+        assert_eq!(store.load("qwerty").unwrap().unlocked.len(), START_KEYS + 1);
+        fs::remove_file(store.path.unwrap()).unwrap();
+    }
+}
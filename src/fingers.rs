@@ -0,0 +1,174 @@
+use crate::keyboard::Keyboard;
+use crate::layout::{Finger, Hand, FINGERS};
+use crate::state::{Action, KeysStats, TimedAction, IDLE_LATENCY};
+
+// What one finger or hand did during a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Workload {
+    pub keystrokes: u32,
+    // Target characters and how many were typed correctly
+    pub total: i32,
+    pub valid: i32,
+    // Sum of the times since the previous keystroke
+    pub interval: u128,
+    pub timed: u32,
+}
+
+impl Workload {
+    fn add(&mut self, other: &Workload) {
+        self.keystrokes += other.keystrokes;
+        self.total += other.total;
+        self.valid += other.valid;
+        self.interval += other.interval;
+        self.timed += other.timed;
+    }
+
+    pub fn accuracy(&self) -> Option<f32> {
+        (self.total > 0).then(|| self.valid as f32 / self.total as f32)
+    }
+
+    pub fn mean_interval(&self) -> Option<f32> {
+        (self.timed > 0).then(|| self.interval as f32 / self.timed as f32)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FingerReport {
+    // Only the fingers and hands that typed something, in keyboard order
+    pub fingers: Vec<(Finger, Workload)>,
+    pub hands: Vec<(Hand, Workload)>,
+    pub keystrokes: u32,
+    // Pairs of keystrokes typed in a row, without a pause or a correction between them
+    pub bigrams: u32,
+    // Bigrams on two different keys of the same finger
+    pub same_finger: u32,
+    // Bigrams switching hands
+    pub alternations: u32,
+}
+
+impl FingerReport {
+    // Characters typed with a modifier count for the finger of their key
+    pub fn new(
+        keyboard: &Keyboard,
+        keys_stats: &KeysStats,
+        actions: &[TimedAction],
+    ) -> FingerReport {
+        let key_of = |c: char| {
+            keyboard
+                .key_for(c)
+                .and_then(|k| Some((k, keyboard.keys[k].finger?)))
+        };
+        let mut fingers = [Workload::default(); FINGERS.len()];
+        let index = |finger: Finger| FINGERS.iter().position(|f| *f == finger).unwrap();
+
+        for (c, (total, valid)) in keys_stats {
+            if let Some((_, finger)) = key_of(*c) {
+                fingers[index(finger)].total += total;
+                fingers[index(finger)].valid += valid;
+            }
+        }
+
+        let mut report = FingerReport::default();
+        let mut previous: Option<(usize, Finger, u128)> = None;
+
+        for action in actions {
+            let Action::Char(c) = action.action else {
+                previous = None;
+                continue;
+            };
+            let Some((key, finger)) = key_of(c) else {
+                previous = None;
+                continue;
+            };
+
+            let workload = &mut fingers[index(finger)];
+            workload.keystrokes += 1;
+            report.keystrokes += 1;
+
+            if let Some((previous_key, previous_finger, time)) = previous {
+                let interval = action.time.saturating_sub(time);
+                if interval <= IDLE_LATENCY {
+                    workload.interval += interval;
+                    workload.timed += 1;
+
+                    report.bigrams += 1;
+                    if previous_finger == finger && previous_key != key {
+                        report.same_finger += 1;
+                    }
+                    if previous_finger.hand() != finger.hand() {
+                        report.alternations += 1;
+                    }
+                }
+            }
+            previous = Some((key, finger, action.time));
+        }
+
+        for (finger, workload) in FINGERS.iter().zip(fingers) {
+            if workload.keystrokes == 0 && workload.total == 0 {
+                continue;
+            }
+            report.fingers.push((*finger, workload));
+
+            match report.hands.iter_mut().find(|(h, _)| *h == finger.hand()) {
+                Some((_, hand)) => hand.add(&workload),
+                None => report.hands.push((finger.hand(), workload)),
+            }
+        }
+
+        report
+    }
+
+    pub fn share(&self, workload: &Workload) -> Option<f32> {
+        (self.keystrokes > 0).then(|| workload.keystrokes as f32 / self.keystrokes as f32)
+    }
+
+    pub fn same_finger_rate(&self) -> Option<f32> {
+        (self.bigrams > 0).then(|| self.same_finger as f32 / self.bigrams as f32)
+    }
+
+    pub fn alternation_rate(&self) -> Option<f32> {
+        (self.bigrams > 0).then(|| self.alternations as f32 / self.bigrams as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::LayoutSource;
+    use std::collections::HashMap;
+
+    #[test]
+    fn splits_the_work_between_fingers() {
+        let keyboard = LayoutSource::Builtin(String::from("qwerty"))
+            .load()
+            .unwrap();
+        // "fr" is a same finger bigram, "rj" and "jf" alternate hands, the pause breaks "fT"
+        let actions: Vec<TimedAction> = [('f', 0), ('r', 100), ('j', 300), ('f', 400), ('T', 3000)]
+            .iter()
+            .map(|(c, time)| TimedAction {
+                action: Action::Char(*c),
+                time: *time,
+                released: None,
+            })
+            .collect();
+        let keys_stats = HashMap::from([('f', (2, 1)), ('j', (1, 1))]);
+
+        let report = FingerReport::new(&keyboard, &keys_stats, &actions);
+
+        assert_eq!(report.keystrokes, 5);
+        assert_eq!(report.bigrams, 3);
+        assert_eq!(report.same_finger_rate(), Some(1.0 / 3.0));
+        assert_eq!(report.alternation_rate(), Some(2.0 / 3.0));
+
+        let (finger, left_index) = report.fingers[0];
+        assert_eq!(finger, Finger::LeftIndex);
+        assert_eq!(left_index.keystrokes, 4);
+        assert_eq!(left_index.accuracy(), Some(0.5));
+        assert_eq!(left_index.mean_interval(), Some(100.0));
+        assert_eq!(report.share(&left_index), Some(0.8));
+
+        assert_eq!(report.hands.len(), 2);
+        assert_eq!(report.hands[1].0, Hand::Right);
+        assert_eq!(report.hands[1].1.keystrokes, 1);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use crate::history::SessionRecord;
+use crate::keyboard::Keyboard;
+use crate::state::{Action, KeysStats, TimedAction, IDLE_LATENCY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapMetric {
+    Accuracy,
+    // Time since the previous keystroke
+    Speed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapScope {
+    Session,
+    // The saved sessions and the current one
+    History,
+}
+
+// What was typed of one character
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeySample {
+    pub total: i32,
+    pub valid: i32,
+    pub latency: u128,
+    pub timed: u32,
+}
+
+impl KeySample {
+    fn add(&mut self, other: &KeySample) {
+        self.total += other.total;
+        self.valid += other.valid;
+        self.latency += other.latency;
+        self.timed += other.timed;
+    }
+}
+
+pub type KeySamples = HashMap<char, KeySample>;
+
+pub fn samples(keys_stats: &KeysStats, actions: &[TimedAction]) -> KeySamples {
+    let mut samples = KeySamples::new();
+
+    for (c, (total, valid)) in keys_stats {
+        let sample = samples.entry(*c).or_default();
+        sample.total += total;
+        sample.valid += valid;
+    }
+
+    for pair in actions.windows(2) {
+        let (previous, action) = (&pair[0], &pair[1]);
+        let latency = action.time.saturating_sub(previous.time);
+        if let Action::Char(c) = action.action {
+            if latency <= IDLE_LATENCY {
+                let sample = samples.entry(c).or_default();
+                sample.latency += latency;
+                sample.timed += 1;
+            }
+        }
+    }
+
+    samples
+}
+
+pub fn merge(into: &mut KeySamples, from: &KeySamples) {
+    for (c, sample) in from {
+        into.entry(*c).or_default().add(sample);
+    }
+}
+
+pub fn history_samples(sessions: &[SessionRecord]) -> KeySamples {
+    let mut all = KeySamples::new();
+    for session in sessions {
+        let keys_stats: KeysStats = session
+            .keys_stats
+            .iter()
+            .map(|(c, total, valid)| (*c, (*total, *valid)))
+            .collect();
+        merge(&mut all, &samples(&keys_stats, &session.actions));
+    }
+    all
+}
+
+pub struct Heatmap {
+    pub metric: HeatmapMetric,
+    pub scope: HeatmapScope,
+    // Samples of the saved sessions, used with the history scope
+    pub past: KeySamples,
+}
+
+impl Heatmap {
+    pub fn new(metric: HeatmapMetric, scope: HeatmapScope) -> Heatmap {
+        Heatmap {
+            metric,
+            scope,
+            past: KeySamples::new(),
+        }
+    }
+
+    // Labels of the buckets, from the best to the worst
+    pub fn legend(&self) -> [&'static str; 5] {
+        match self.metric {
+            HeatmapMetric::Accuracy => [">=98%", ">=95%", ">=90%", ">=80%", "<80%"],
+            HeatmapMetric::Speed => ["fastest", "fast", "average", "slow", "slowest"],
+        }
+    }
+
+    // Bucket of each key with data, 0 being the best. Characters typed with a modifier count
+    // for their key. Speed is relative to the average latency of the keys.
+    pub fn buckets(&self, keyboard: &Keyboard, session: &KeySamples) -> HashMap<usize, usize> {
+        let mut chars = session.clone();
+        if self.scope == HeatmapScope::History {
+            merge(&mut chars, &self.past);
+        }
+
+        let mut keys: HashMap<usize, KeySample> = HashMap::new();
+        for (c, sample) in &chars {
+            if let Some(key) = keyboard.key_for(*c) {
+                keys.entry(key).or_default().add(sample);
+            }
+        }
+
+        match self.metric {
+            HeatmapMetric::Accuracy => keys
+                .into_iter()
+                .filter(|(_, s)| s.total > 0)
+                .map(|(key, s)| {
+                    let accuracy = s.valid as f32 / s.total as f32;
+                    let bucket = [0.98, 0.95, 0.9, 0.8]
+                        .iter()
+                        .position(|limit| accuracy >= *limit)
+                        .unwrap_or(4);
+                    (key, bucket)
+                })
+                .collect(),
+            HeatmapMetric::Speed => {
+                let latencies: Vec<(usize, f32)> = keys
+                    .into_iter()
+                    .filter(|(_, s)| s.timed > 0)
+                    .map(|(key, s)| (key, s.latency as f32 / s.timed as f32))
+                    .collect();
+                let average =
+                    latencies.iter().map(|(_, l)| l).sum::<f32>() / latencies.len().max(1) as f32;
+                latencies
+                    .into_iter()
+                    .map(|(key, latency)| {
+                        let bucket = [0.8, 0.95, 1.1, 1.3]
+                            .iter()
+                            .position(|limit| latency <= average * limit)
+                            .unwrap_or(4);
+                        (key, bucket)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::LayoutSource;
+
+    fn typed(chars: &[(char, u128)]) -> Vec<TimedAction> {
+        chars
+            .iter()
+            .map(|(c, time)| TimedAction {
+                action: Action::Char(*c),
+                time: *time,
+                released: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collects_latencies() {
+        let actions = typed(&[('a', 0), ('b', 100), ('a', 300), ('b', 5000)]);
+        let samples = samples(&HashMap::from([('a', (2, 1))]), &actions);
+
+        assert_eq!(
+            samples[&'a'],
+            KeySample {
+                total: 2,
+                valid: 1,
+                latency: 200,
+                timed: 1
+            }
+        );
+        // The pause before the last keystroke isn't counted
+        assert_eq!(samples[&'b'].timed, 1);
+    }
+
+    #[test]
+    fn buckets_keys() {
+        let keyboard = LayoutSource::Builtin(String::from("qwerty"))
+            .load()
+            .unwrap();
+        let key = |c| keyboard.key_for(c).unwrap();
+        let stats = HashMap::from([('a', (10, 10)), ('A', (10, 0)), ('s', (10, 9))]);
+        let actions = typed(&[('x', 0), ('a', 100), ('s', 200), ('d', 500)]);
+        let session = samples(&stats, &actions);
+
+        let accuracy = Heatmap::new(HeatmapMetric::Accuracy, HeatmapScope::Session);
+        let buckets = accuracy.buckets(&keyboard, &session);
+        assert_eq!(buckets[&key('a')], 4);
+        assert_eq!(buckets[&key('s')], 2);
+        assert!(!buckets.contains_key(&key('d')));
+
+        let mut speed = Heatmap::new(HeatmapMetric::Speed, HeatmapScope::History);
+        speed.past = samples(&HashMap::new(), &typed(&[('f', 0), ('s', 1000)]));
+        let buckets = speed.buckets(&keyboard, &session);
+        assert_eq!(buckets[&key('a')], 0);
+        assert_eq!(buckets[&key('s')], 4);
+    }
+}
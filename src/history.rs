@@ -0,0 +1,322 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::clock::Clock;
+use crate::state::{
+    Action, AlignmentMode, CorrectionPolicy, NgramStats, StateStats, TestMode, TimedAction,
+    TypeTestState,
+};
+
+// Bump when `SessionRecord` changes in a way older readers can't handle, and teach
+// `upgrade` how to turn the previous version into the new one.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug)]
+pub enum HistoryError {
+    NoDataDir,
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnknownVersion(u32),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::NoDataDir => write!(f, "no data directory available"),
+            HistoryError::Io(e) => write!(f, "{}", e),
+            HistoryError::Json(e) => write!(f, "invalid history record: {}", e),
+            HistoryError::UnknownVersion(v) => {
+                write!(f, "history record has unknown schema version {}", v)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        HistoryError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(e: serde_json::Error) -> Self {
+        HistoryError::Json(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    pub source: String,
+    pub mode: TestMode,
+    // Missing from records written before edit distance alignment existed
+    #[serde(default)]
+    pub alignment: AlignmentMode,
+    #[serde(default)]
+    pub correction: CorrectionPolicy,
+    // Unix timestamps in milliseconds
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub words_per_minute: Option<f32>,
+    pub precision: Option<f32>,
+    pub keys_precision: Vec<(char, f32)>,
+    // (key, total, valid), the counts `keys_precision` is derived from
+    pub keys_stats: Vec<(char, i32, i32)>,
+    // Unix timestamps in milliseconds as well
+    pub actions: Vec<TimedAction>,
+    // Missing from records written before n-gram latencies were kept
+    #[serde(default)]
+    pub ngrams: Vec<NgramStats>,
+}
+
+impl SessionRecord {
+    pub fn new(
+        source: &str,
+        type_test: &TypeTestState,
+        stats: &StateStats,
+        clock: &dyn Clock,
+    ) -> SessionRecord {
+        let started_at = type_test.started_at.unwrap_or(0);
+        let ended_at = type_test.finished_at.unwrap_or(started_at);
+
+        let mut keys_stats: Vec<_> = stats
+            .keys_stats
+            .iter()
+            .map(|(k, (total, valid))| (*k, *total, *valid))
+            .collect();
+        keys_stats.sort_by_key(|k| k.0);
+
+        SessionRecord {
+            source: source.to_string(),
+            mode: type_test.mode,
+            alignment: type_test.alignment,
+            correction: type_test.correction,
+            started_at: clock.to_unix_millis(started_at),
+            ended_at: clock.to_unix_millis(ended_at),
+            words_per_minute: stats.words_per_minute,
+            precision: stats.precision,
+            keys_precision: stats.keys_precision.clone().unwrap_or_default(),
+            keys_stats,
+            actions: type_test
+                .actions
+                .iter()
+                .map(|a| TimedAction {
+                    action: a.action.clone(),
+                    time: clock.to_unix_millis(a.time) as u128,
+                    released: a.released.map(|r| clock.to_unix_millis(r) as u128),
+                })
+                .collect(),
+            ngrams: stats.ngrams.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    session: &'a SessionRecord,
+}
+
+#[derive(Deserialize)]
+struct RawEnvelope {
+    version: u32,
+    session: Value,
+}
+
+// Earlier than any Unix timestamp in milliseconds of a real session, 2001-09-09
+const UNIX_MILLIS_FLOOR: u128 = 1_000_000_000_000;
+
+fn upgrade(version: u32, session: Value) -> Result<SessionRecord, HistoryError> {
+    match version {
+        1 => {
+            // Some version 1 records kept the action times of the monotonic clock, relative to
+            // the start of the process. The first character was typed when the session started.
+            let mut record: SessionRecord = serde_json::from_value(session)?;
+            let first = record
+                .actions
+                .iter()
+                .find(|a| matches!(a.action, Action::Char(_)))
+                .map(|a| a.time);
+            if let Some(first) = first.filter(|t| *t < UNIX_MILLIS_FLOOR) {
+                let offset = (record.started_at as u128).saturating_sub(first);
+                for action in &mut record.actions {
+                    action.time += offset;
+                    action.released = action.released.map(|r| r + offset);
+                }
+            }
+            Ok(record)
+        }
+        2 => Ok(serde_json::from_value(session)?),
+        v => Err(HistoryError::UnknownVersion(v)),
+    }
+}
+
+// Append-only JSON lines file, one versioned session per line.
+#[derive(Debug, Clone)]
+pub struct History {
+    path: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(path: Option<PathBuf>) -> History {
+        History { path }
+    }
+
+    pub fn open_default() -> History {
+        History::new(dirs::data_dir().map(|d| d.join("blitzkey").join("history.jsonl")))
+    }
+
+    pub fn append(&self, session: &SessionRecord) -> Result<(), HistoryError> {
+        let path = self.path.as_ref().ok_or(HistoryError::NoDataDir)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut line = serde_json::to_string(&Envelope {
+            version: SCHEMA_VERSION,
+            session,
+        })?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn load(&self) -> Result<Vec<SessionRecord>, HistoryError> {
+        Ok(self.load_with_skipped()?.0)
+    }
+
+    // Also returns how many lines could not be read. A crash while appending leaves a
+    // truncated line, which shouldn't make the rest of the history unreadable.
+    pub fn load_with_skipped(&self) -> Result<(Vec<SessionRecord>, usize), HistoryError> {
+        let path = self.path.as_ref().ok_or(HistoryError::NoDataDir)?;
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((Vec::new(), 0)),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut sessions = Vec::new();
+        let mut skipped = 0;
+        for line in BufReader::new(file).split(b'\n') {
+            let line = line?;
+            let Ok(line) = std::str::from_utf8(&line) else {
+                skipped += 1;
+                continue;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let session = serde_json::from_str::<RawEnvelope>(line)
+                .map_err(HistoryError::from)
+                .and_then(|raw| upgrade(raw.version, raw.session));
+            match session {
+                Ok(session) => sessions.push(session),
+                Err(_) => skipped += 1,
+            }
+        }
+        Ok((sessions, skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history(name: &str) -> History {
+        let path = std::env::temp_dir()
+            .join(format!("blitzkey-{}-{}", name, std::process::id()))
+            .join("history.jsonl");
+        let _ = fs::remove_file(&path);
+        History::new(Some(path))
+    }
+
+    #[test]
+    fn append_then_load() {
+        let history = temp_history("append");
+        let record = SessionRecord {
+            source: String::from("main.rs"),
+            mode: TestMode::Full,
+            alignment: AlignmentMode::EditDistance,
+            correction: CorrectionPolicy::StopOnError,
+            started_at: 1000,
+            ended_at: 61000,
+            words_per_minute: Some(42.0),
+            precision: Some(0.95),
+            keys_precision: vec![('a', 1.0)],
+            keys_stats: vec![('a', 2, 2)],
+            actions: vec![TimedAction {
+                action: Action::Char('a'),
+                time: 1000,
+                released: None,
+            }],
+            ngrams: Vec::new(),
+        };
+
+        history.append(&record).unwrap();
+        history.append(&record).unwrap();
+
+        let sessions = history.load().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[1].source, "main.rs");
+        assert_eq!(sessions[1].keys_stats, vec![('a', 2, 2)]);
+        assert_eq!(sessions[1].correction, CorrectionPolicy::StopOnError);
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let history = temp_history("corrupt");
+        let record = r#"{"version":1,"session":{"source":"a.txt","mode":"full","started_at":0,"ended_at":1,"words_per_minute":null,"precision":null,"keys_precision":[],"keys_stats":[],"actions":[]}}"#;
+        let path = history.path.clone().unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // Cut short by a crash
+        fs::write(&path, format!("{}\n{}\n{}", record, record, &record[..40])).unwrap();
+
+        let (sessions, skipped) = history.load_with_skipped().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(skipped, 1);
+        assert_eq!(history.load().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reads_version_1_records() {
+        let session = r#"{"version":1,"session":{"source":"a.txt","mode":"full","started_at":0,"ended_at":1,"words_per_minute":null,"precision":null,"keys_precision":[],"keys_stats":[],"actions":[{"action":{"Char":"a"},"time":0},{"action":"Backspace","time":1}]}}"#;
+        let raw: RawEnvelope = serde_json::from_str(session).unwrap();
+        let record = upgrade(raw.version, raw.session).unwrap();
+        assert_eq!(record.source, "a.txt");
+        assert_eq!(record.actions.len(), 2);
+        assert_eq!(record.alignment, AlignmentMode::Strict);
+        assert_eq!(record.correction, CorrectionPolicy::Free);
+    }
+
+    #[test]
+    fn moves_process_relative_action_times() {
+        let session = r#"{"version":1,"session":{"source":"a.txt","mode":"full","started_at":1700000005000,"ended_at":1700000006000,"words_per_minute":null,"precision":null,"keys_precision":[],"keys_stats":[],"actions":[{"action":"Backspace","time":4000},{"action":{"Char":"a"},"time":5000,"released":5080},{"action":{"Char":"b"},"time":6000}]}}"#;
+        let raw: RawEnvelope = serde_json::from_str(session).unwrap();
+        let record = upgrade(raw.version, raw.session).unwrap();
+        let times: Vec<u128> = record.actions.iter().map(|a| a.time).collect();
+        assert_eq!(times, vec![1700000004000, 1700000005000, 1700000006000]);
+        assert_eq!(record.actions[1].released, Some(1700000005080));
+
+        // Already Unix timestamps
+        let session = session.replace(r#""time":5000"#, r#""time":1700000005000"#);
+        let raw: RawEnvelope = serde_json::from_str(&session).unwrap();
+        let record = upgrade(raw.version, raw.session).unwrap();
+        assert_eq!(record.actions[0].time, 4000);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        assert!(matches!(
+            upgrade(SCHEMA_VERSION + 1, Value::Null),
+            Err(HistoryError::UnknownVersion(_))
+        ));
+    }
+}
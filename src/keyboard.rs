@@ -1,83 +1,566 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::Path,
+};
 
+use crate::layout::{Hand, KeyDef, LayoutFile, Level, Special, TouchMap, LEVELS};
 use crate::utils::read_file_to_string;
 
-type TouchMap = HashMap<usize, char>;
+pub const LAYOUTS: [(&str, &str); 8] = [
+    ("qwerty", include_str!("../resources/layouts/qwerty.toml")),
+    ("dvorak", include_str!("../resources/layouts/dvorak.toml")),
+    ("colemak", include_str!("../resources/layouts/colemak.toml")),
+    (
+        "colemak_dh",
+        include_str!("../resources/layouts/colemak_dh.toml"),
+    ),
+    ("workman", include_str!("../resources/layouts/workman.toml")),
+    ("azerty", include_str!("../resources/layouts/azerty.toml")),
+    ("qwertz", include_str!("../resources/layouts/qwertz.toml")),
+    ("uk_iso", include_str!("../resources/layouts/uk_iso.toml")),
+];
 
-fn parse_layout(layout: String) -> Result<(String, TouchMap), KeyboardError> {
-    let res = layout.split("\n\n").collect::<Vec<&str>>();
+pub const DEFAULT_LAYOUT: &str = "qwerty";
 
-    if res.len() != 2 {
-        return Err(KeyboardError::LayoutParsingError);
+// How much the keyboard shows of the key to press next
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Guide {
+    #[default]
+    Off,
+    Keys,
+    // Also names the fingers pressing them
+    Fingers,
+}
+
+pub const GUIDES: [(&str, Guide); 3] = [
+    ("off", Guide::Off),
+    ("keys", Guide::Keys),
+    ("fingers", Guide::Fingers),
+];
+
+pub fn layout(name: &str) -> Option<&'static str> {
+    LAYOUTS.iter().find(|(n, _)| *n == name).map(|(_, l)| *l)
+}
+
+#[derive(Debug)]
+pub enum KeyboardError {
+    LayoutFileNotFound(String),
+    Io(String, io::Error),
+    UnknownLayout(String),
+    // Lines and columns start at 1
+    LayoutParsingError {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    // A line of the mask doesn't have the width of the matching line of the drawing, 0 when
+    // the line is missing from one of them
+    DimensionMismatch {
+        line: usize,
+        drawing: usize,
+        mask: usize,
+    },
+    // Keys are named by their row and column, in key units
+    DuplicateLegend {
+        legend: char,
+        first: (usize, f32),
+        second: (usize, f32),
+    },
+    InvalidGeometry {
+        row: usize,
+        col: f32,
+        message: String,
+    },
+    // A character of the text no key of the layout can type
+    UnreachableChar {
+        c: char,
+        line: usize,
+        column: usize,
+        count: usize,
+    },
+    // Rows and columns of the letters of the emulated layout with no key under them
+    IncompatibleLayouts(Vec<(usize, f32)>),
+}
+
+impl fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyboardError::LayoutFileNotFound(path) => write!(f, "{}: file not found", path),
+            KeyboardError::Io(path, e) => write!(f, "{}: {}", path, e),
+            KeyboardError::UnknownLayout(name) => write!(f, "no built-in layout named {}", name),
+            KeyboardError::LayoutParsingError {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            KeyboardError::DimensionMismatch {
+                line,
+                drawing,
+                mask,
+            } => write!(
+                f,
+                "line {}: the mask is {} characters wide but the drawing is {}",
+                line, mask, drawing
+            ),
+            KeyboardError::DuplicateLegend {
+                legend,
+                first,
+                second,
+            } => write!(
+                f,
+                "{:?} is typed by the keys at row {} column {} and row {} column {}",
+                legend, first.0, first.1, second.0, second.1
+            ),
+            KeyboardError::InvalidGeometry { row, col, message } => {
+                write!(f, "key at row {} column {}: {}", row, col, message)
+            }
+            KeyboardError::UnreachableChar {
+                c,
+                line,
+                column,
+                count,
+            } => write!(
+                f,
+                "line {}, column {}: no key types {:?} ({} in the text)",
+                line, column, c, count
+            ),
+            KeyboardError::IncompatibleLayouts(places) => {
+                let places: Vec<String> = places
+                    .iter()
+                    .map(|(row, col)| format!("row {} column {}", row, col))
+                    .collect();
+                write!(
+                    f,
+                    "the physical layout has no key at {}, where the emulated layout has letters",
+                    places.join(", ")
+                )
+            }
+        }
     }
+}
 
-    match (res.first(), res.last()) {
-        (Some(layout), Some(mapping)) => {
-            let mut touch_map = HashMap::new();
+// Where the keyboard layout comes from
+#[derive(Debug, Clone)]
+pub enum LayoutSource {
+    Builtin(String),
+    File(String),
+}
 
-            mapping.chars().enumerate().for_each(|(i, c)| {
-                if !" |-_".contains(c) {
-                    touch_map.insert(i, c);
+impl LayoutSource {
+    // Files other than .toml are read in the older drawing and mask format
+    pub fn load_file(&self) -> Result<LayoutFile, KeyboardError> {
+        match self {
+            LayoutSource::Builtin(name) => LayoutFile::from_toml(
+                layout(name).ok_or_else(|| KeyboardError::UnknownLayout(name.clone()))?,
+            ),
+            LayoutSource::File(path) => {
+                let text = read_file_to_string(path).map_err(|e| match e.kind() {
+                    io::ErrorKind::NotFound => KeyboardError::LayoutFileNotFound(path.clone()),
+                    _ => KeyboardError::Io(path.clone(), e),
+                })?;
+                if path.ends_with(".toml") {
+                    LayoutFile::from_toml(&text)
                 } else {
-                    touch_map.insert(i, ' ');
+                    let name = Path::new(path)
+                        .file_stem()
+                        .map_or(String::new(), |s| s.to_string_lossy().to_string());
+                    LayoutFile::from_legacy(&name, &text)
                 }
-            });
+            }
+        }
+    }
 
-            Ok((String::from(*layout), touch_map))
+    // Fails on the first problem of the layout, `layout check` lists them all
+    pub fn load(&self) -> Result<Keyboard, KeyboardError> {
+        let file = self.load_file()?;
+        match file.problems().into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(Keyboard::new(file)),
         }
-        _ => Err(KeyboardError::LayoutParsingError),
     }
 }
 
-#[derive(Debug)]
-pub enum KeyboardError {
-    LayoutFileNotFound,
-    LayoutParsingError,
+// Every problem of the layout, then the characters of the text it can't type
+pub fn check(source: &LayoutSource, text: Option<&str>) -> Vec<KeyboardError> {
+    let file = match source.load_file() {
+        Ok(file) => file,
+        Err(e) => return vec![e],
+    };
+    let problems = file.problems();
+    match text {
+        Some(text) if problems.is_empty() => Keyboard::new(file).unreachable_chars(text),
+        _ => problems,
+    }
 }
 
 pub struct Keyboard {
+    // Of the layout file
+    pub name: String,
+    pub keys: Vec<KeyDef>,
+    // The drawing shown under the text
     pub layout: String,
     pub touch_map: TouchMap,
-    pub active_keys: HashMap<char, u128>,
+    // The key typing each character and the modifier it needs, preferring the fewest
+    pub strokes: HashMap<char, (usize, Level)>,
+    // Indices of the highlighted keys, until when they stay highlighted
+    pub active_keys: HashMap<usize, u128>,
+    // After a wrong key, the keys that were expected and the ones pressed instead
+    pub missed_keys: HashMap<usize, u128>,
+    pub wrong_keys: HashMap<usize, u128>,
+    // Keys not practiced yet, drawn dimmed
+    pub locked_keys: HashSet<usize>,
+    pub guide: Guide,
 }
 
 impl Keyboard {
-    pub fn new(raw_layout: String) -> Result<Keyboard, KeyboardError> {
-        let (layout, touch_map) = parse_layout(raw_layout)?;
+    pub fn new(file: LayoutFile) -> Keyboard {
+        let (layout, touch_map) = file.render();
+
+        let mut strokes = HashMap::new();
+        for level in LEVELS {
+            for (i, key) in file.keys.iter().enumerate() {
+                if let Some(c) = key.legend(level) {
+                    strokes.entry(c).or_insert((i, level));
+                }
+            }
+        }
 
-        Ok(Keyboard {
+        Keyboard {
+            name: file.name,
+            strokes,
+            keys: file.keys,
             layout,
             touch_map,
             active_keys: HashMap::new(),
+            missed_keys: HashMap::new(),
+            wrong_keys: HashMap::new(),
+            locked_keys: HashSet::new(),
+            guide: Guide::Off,
+        }
+    }
+
+    pub fn key_for(&self, c: char) -> Option<usize> {
+        self.strokes.get(&c).map(|(key, _)| *key)
+    }
+
+    // Keys without a hand are placed by their side of the keyboard
+    fn hand_of(&self, key: usize) -> Hand {
+        let key = &self.keys[key];
+        key.hand().unwrap_or_else(|| {
+            let right = self
+                .keys
+                .iter()
+                .map(|k| k.col + k.width)
+                .fold(0.0, f32::max);
+            if key.col + key.width / 2.0 < right / 2.0 {
+                Hand::Left
+            } else {
+                Hand::Right
+            }
         })
     }
 
-    pub fn key_pressed(&mut self, key: char) {
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis()
-            + 200;
+    // The modifier key held while pressing `key`: the Shift of the other hand, or AltGr
+    fn modifier_key(&self, key: usize, level: Level) -> Option<usize> {
+        let find = |special: Special, hand: Option<Hand>| {
+            (0..self.keys.len()).find(|i| {
+                self.keys[*i].special == Some(special)
+                    && hand.is_none_or(|hand| self.hand_of(*i) == hand)
+            })
+        };
+        match level {
+            Level::Base => None,
+            Level::Shift => {
+                let other = match self.hand_of(key) {
+                    Hand::Left => Hand::Right,
+                    Hand::Right => Hand::Left,
+                };
+                find(Special::Shift, Some(other)).or_else(|| find(Special::Shift, None))
+            }
+            Level::AltGr => {
+                find(Special::AltGr, None).or_else(|| find(Special::Alt, Some(Hand::Right)))
+            }
+        }
+    }
 
-        self.active_keys.insert(key.to_ascii_uppercase(), time);
+    // The keys pressed together to type this character
+    pub fn keys_for(&self, c: char) -> Vec<usize> {
+        match self.strokes.get(&c) {
+            Some((key, level)) => std::iter::once(*key)
+                .chain(self.modifier_key(*key, *level))
+                .collect(),
+            None => Vec::new(),
+        }
     }
 
-    pub fn tick(&mut self) {
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    // One error per character of the text that can't be typed, at its first occurrence
+    pub fn unreachable_chars(&self, text: &str) -> Vec<KeyboardError> {
+        let mut found: Vec<KeyboardError> = Vec::new();
 
-        let mut keys_to_remove = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().chain(['\n']).enumerate() {
+                if self.key_for(c).is_some() {
+                    continue;
+                }
+                let seen = found.iter_mut().find_map(|e| match e {
+                    KeyboardError::UnreachableChar {
+                        c: other, count, ..
+                    } if *other == c => Some(count),
+                    _ => None,
+                });
+                match seen {
+                    Some(count) => *count += 1,
+                    None => found.push(KeyboardError::UnreachableChar {
+                        c,
+                        line: n + 1,
+                        column: column + 1,
+                        count: 1,
+                    }),
+                }
+            }
+        }
 
-        for (key, time_pressed) in &self.active_keys {
-            if time > *time_pressed {
-                keys_to_remove.push(*key);
+        found
+    }
+
+    pub fn key_pressed(&mut self, key: char, time: u128) {
+        for i in self.keys_for(key) {
+            self.active_keys.insert(i, time + 200);
+        }
+    }
+
+    // Mistakes stay on screen longer than keystrokes so there is time to compare them
+    pub fn key_missed(&mut self, expected: char, typed: char, time: u128) {
+        for i in self.keys_for(expected) {
+            self.missed_keys.insert(i, time + 600);
+        }
+        for i in self.keys_for(typed) {
+            self.wrong_keys.insert(i, time + 600);
+        }
+    }
+
+    pub fn tick(&mut self, time: u128) {
+        for keys in [
+            &mut self.active_keys,
+            &mut self.missed_keys,
+            &mut self.wrong_keys,
+        ] {
+            keys.retain(|_, until| time <= *until);
+        }
+    }
+}
+
+// Translates characters typed on the physical layout into the ones typed by the key at the
+// same place on the emulated layout, with the same modifiers.
+pub struct Emulation {
+    chars: HashMap<char, char>,
+}
+
+impl Emulation {
+    pub fn new(physical: &Keyboard, target: &Keyboard) -> Result<Emulation, KeyboardError> {
+        let places = |keyboard: &Keyboard| {
+            let mut places: Vec<(usize, i64, usize)> = keyboard
+                .keys
+                .iter()
+                .enumerate()
+                .filter(|(_, k)| k.base.is_some())
+                .map(|(i, k)| (k.row, (k.col * 4.0).round() as i64, i))
+                .collect();
+            places.sort();
+            places
+        };
+        let from: HashMap<(usize, i64), usize> = places(physical)
+            .into_iter()
+            .map(|(row, col, i)| ((row, col), i))
+            .collect();
+
+        // Keys found on one layout only, like the extra key of ISO keyboards, are left out
+        // unless the emulated layout types a letter with them
+        let mut pairs = Vec::new();
+        let mut missing = Vec::new();
+        for (row, col, b) in places(target) {
+            match from.get(&(row, col)) {
+                Some(a) => pairs.push((*a, b)),
+                None if target.keys[b].base.is_some_and(char::is_alphabetic) => {
+                    missing.push((row, target.keys[b].col))
+                }
+                None => {}
+            }
+        }
+        if !missing.is_empty() || pairs.is_empty() {
+            return Err(KeyboardError::IncompatibleLayouts(missing));
+        }
+
+        let mut chars = HashMap::new();
+        for level in LEVELS {
+            for (a, b) in &pairs {
+                if let (Some(from), Some(to)) = (
+                    physical.keys[*a].legend(level),
+                    target.keys[*b].legend(level),
+                ) {
+                    chars.entry(from).or_insert(to);
+                }
+            }
+        }
+
+        Ok(Emulation { chars })
+    }
+
+    pub fn translate(&self, c: char) -> char {
+        *self.chars.get(&c).unwrap_or(&c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> Keyboard {
+        LayoutSource::Builtin(name.to_string()).load().unwrap()
+    }
+
+    #[test]
+    fn builtin_layouts_cover_the_alphabet() {
+        for (name, _) in LAYOUTS {
+            let keyboard = load(name);
+
+            for key in ('a'..='z')
+                .chain('A'..='Z')
+                .chain([' ', '\n', '\t', ',', '.'])
+            {
+                assert!(
+                    keyboard.key_for(key).is_some(),
+                    "{} has no {:?} key",
+                    name,
+                    key
+                );
             }
         }
+    }
 
-        for key in keys_to_remove {
-            self.active_keys.remove(&key);
+    #[test]
+    fn builtin_layouts_have_no_problems() {
+        for (name, _) in LAYOUTS {
+            let problems = check(&LayoutSource::Builtin(name.to_string()), None);
+            assert!(problems.is_empty(), "{}: {:?}", name, problems);
         }
     }
+
+    #[test]
+    fn finds_unreachable_chars() {
+        let problems = load("qwerty").unreachable_chars("fn main() {}\n// déjà vu, déjà\n");
+
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(
+            problems[0],
+            KeyboardError::UnreachableChar {
+                c: 'é',
+                line: 2,
+                column: 5,
+                count: 2
+            }
+        ));
+        assert!(matches!(
+            problems[1],
+            KeyboardError::UnreachableChar { c: 'à', .. }
+        ));
+    }
+
+    #[test]
+    fn finds_keys_of_shifted_characters() {
+        let keyboard = load("qwerty");
+        let key = |c| &keyboard.keys[keyboard.key_for(c).unwrap()];
+
+        assert_eq!(key('a').base, Some('a'));
+        assert_eq!(key('A').base, Some('a'));
+        assert_eq!(key(':').base, Some(';'));
+        assert_eq!(key('\n').special, Some(crate::layout::Special::Enter));
+        assert!(keyboard.key_for('€').is_none());
+        assert_eq!(keyboard.strokes.get(&'{').map(|s| s.1), Some(Level::Shift));
+        assert_eq!(
+            load("uk_iso").keys[load("uk_iso").key_for('€').unwrap()].base,
+            Some('4')
+        );
+    }
+
+    #[test]
+    fn lights_the_modifier_keys() {
+        let mut keyboard = load("qwerty");
+        let special = |keyboard: &Keyboard, c: char| {
+            keyboard
+                .keys_for(c)
+                .iter()
+                .map(|k| (keyboard.keys[*k].special, keyboard.hand_of(*k)))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(special(&keyboard, 'a'), vec![(None, Hand::Left)]);
+        assert_eq!(
+            special(&keyboard, 'A'),
+            vec![(None, Hand::Left), (Some(Special::Shift), Hand::Right)]
+        );
+        assert_eq!(
+            special(&keyboard, '{'),
+            vec![(None, Hand::Right), (Some(Special::Shift), Hand::Left)]
+        );
+        assert_eq!(
+            special(&load("uk_iso"), '€'),
+            vec![(None, Hand::Left), (Some(Special::AltGr), Hand::Right)]
+        );
+
+        keyboard.key_pressed('P', 0);
+        assert_eq!(keyboard.active_keys.len(), 2);
+        keyboard.tick(201);
+        assert!(keyboard.active_keys.is_empty());
+
+        keyboard.key_missed('a', 'S', 0);
+        assert_eq!(keyboard.missed_keys.len(), 1);
+        assert_eq!(keyboard.wrong_keys.len(), 2);
+        keyboard.tick(601);
+        assert!(keyboard.missed_keys.is_empty() && keyboard.wrong_keys.is_empty());
+    }
+
+    #[test]
+    fn emulates_layouts() {
+        let emulation = Emulation::new(&load("qwerty"), &load("colemak")).unwrap();
+
+        let typed: String = "fjkl; Qe-\n"
+            .chars()
+            .map(|c| emulation.translate(c))
+            .collect();
+        assert_eq!(typed, "tneio Qf-\n");
+        // Shifted characters follow their key
+        assert_eq!(emulation.translate(':'), 'O');
+
+        // The extra ISO key and the ANSI backslash key are left out
+        let emulation = Emulation::new(&load("uk_iso"), &load("azerty")).unwrap();
+        assert_eq!(emulation.translate('q'), 'a');
+        assert_eq!(emulation.translate('\\'), '<');
+        let emulation = Emulation::new(&load("qwertz"), &load("qwerty")).unwrap();
+        assert_eq!(emulation.translate('z'), 'y');
+        assert_eq!(emulation.translate('#'), '#');
+
+        let mut file = LayoutSource::Builtin(String::from("dvorak"))
+            .load_file()
+            .unwrap();
+        let a = file.keys.iter_mut().find(|k| k.base == Some('a')).unwrap();
+        a.col += 20.0;
+        let Err(error) = Emulation::new(&load("qwerty"), &Keyboard::new(file)) else {
+            panic!("the layouts should not match");
+        };
+        assert!(matches!(
+            &error,
+            KeyboardError::IncompatibleLayouts(places) if places == &vec![(2, 21.75)]
+        ));
+        assert!(error.to_string().contains("row 2 column 21.75"));
+    }
+
+    #[test]
+    fn missing_layout_file() {
+        assert!(matches!(
+            LayoutSource::File(String::from("/nonexistent/layout.txt")).load(),
+            Err(KeyboardError::LayoutFileNotFound(_))
+        ));
+    }
 }
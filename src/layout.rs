@@ -0,0 +1,795 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::keyboard::KeyboardError;
+
+// Characters of the drawing per key unit, a letter key being one unit wide
+const UNIT: f32 = 4.0;
+
+// Index of a character of the drawing to the index of the key drawn there
+pub type TouchMap = HashMap<usize, usize>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Finger {
+    LeftPinky,
+    LeftRing,
+    LeftMiddle,
+    LeftIndex,
+    LeftThumb,
+    RightThumb,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightPinky,
+}
+
+// From the left to the right of the keyboard
+pub const FINGERS: [Finger; 10] = [
+    Finger::LeftPinky,
+    Finger::LeftRing,
+    Finger::LeftMiddle,
+    Finger::LeftIndex,
+    Finger::LeftThumb,
+    Finger::RightThumb,
+    Finger::RightIndex,
+    Finger::RightMiddle,
+    Finger::RightRing,
+    Finger::RightPinky,
+];
+
+impl Finger {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Finger::LeftPinky => "left pinky",
+            Finger::LeftRing => "left ring",
+            Finger::LeftMiddle => "left middle",
+            Finger::LeftIndex => "left index",
+            Finger::LeftThumb => "left thumb",
+            Finger::RightThumb => "right thumb",
+            Finger::RightIndex => "right index",
+            Finger::RightMiddle => "right middle",
+            Finger::RightRing => "right ring",
+            Finger::RightPinky => "right pinky",
+        }
+    }
+
+    pub fn hand(&self) -> Hand {
+        match self {
+            Finger::LeftPinky
+            | Finger::LeftRing
+            | Finger::LeftMiddle
+            | Finger::LeftIndex
+            | Finger::LeftThumb => Hand::Left,
+            _ => Hand::Right,
+        }
+    }
+}
+
+// Keys without a legend of their own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Special {
+    Backspace,
+    Tab,
+    CapsLock,
+    Enter,
+    Shift,
+    Ctrl,
+    Alt,
+    AltGr,
+    Space,
+}
+
+impl Special {
+    fn label(&self) -> &'static str {
+        match self {
+            Special::Backspace => "<-",
+            Special::Tab => "Tab",
+            Special::CapsLock => "Caps",
+            Special::Enter => "Enter",
+            Special::Shift => "Shift",
+            Special::Ctrl => "Ctrl",
+            Special::Alt => "Alt",
+            Special::AltGr => "AltGr",
+            Special::Space => "Space",
+        }
+    }
+
+    fn typed(&self) -> Option<char> {
+        match self {
+            Special::Tab => Some('\t'),
+            Special::Enter => Some('\n'),
+            Special::Space => Some(' '),
+            _ => None,
+        }
+    }
+}
+
+// Modifier held to type a legend of a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Base,
+    Shift,
+    AltGr,
+}
+
+pub const LEVELS: [Level; 3] = [Level::Base, Level::Shift, Level::AltGr];
+
+fn one() -> f32 {
+    1.0
+}
+
+fn is_one(width: &f32) -> bool {
+    *width == 1.0
+}
+
+fn one_row() -> usize {
+    1
+}
+
+fn is_one_row(height: &usize) -> bool {
+    *height == 1
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyDef {
+    pub row: usize,
+    // Distance from the left edge of the keyboard, in key units
+    pub col: f32,
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub width: f32,
+    // Number of rows covered, e.g. 2 for an ISO Enter
+    #[serde(default = "one_row", skip_serializing_if = "is_one_row")]
+    pub height: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finger: Option<Finger>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hand: Option<Hand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub special: Option<Special>,
+    // Drawn on the key instead of its legend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<char>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shift: Option<char>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub altgr: Option<char>,
+}
+
+impl KeyDef {
+    // The character typed without modifiers, whitespace included
+    pub fn typed(&self) -> Option<char> {
+        self.base.or(self.special.and_then(|s| s.typed()))
+    }
+
+    pub fn legend(&self, level: Level) -> Option<char> {
+        match level {
+            Level::Base => self.typed(),
+            Level::Shift => self.shift,
+            Level::AltGr => self.altgr,
+        }
+    }
+
+    pub fn hand(&self) -> Option<Hand> {
+        self.hand.or(self.finger.map(|f| f.hand()))
+    }
+
+    pub fn display(&self) -> String {
+        match (&self.label, self.special, self.base) {
+            (Some(label), _, _) => label.clone(),
+            (None, Some(special), _) => special.label().to_string(),
+            (None, None, Some(base)) => {
+                let mut upper = base.to_uppercase();
+                match (upper.next(), upper.next()) {
+                    (Some(u), None) => u.to_string(),
+                    // e.g. 'ß' has no single uppercase character
+                    _ => base.to_string(),
+                }
+            }
+            (None, None, None) => String::new(),
+        }
+    }
+
+    // First and last column of the drawing covered by the key, borders included
+    fn span(&self) -> (usize, usize) {
+        (
+            (self.col * UNIT).round() as usize,
+            ((self.col + self.width) * UNIT).round() as usize,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutFile {
+    pub name: String,
+    #[serde(rename = "key")]
+    pub keys: Vec<KeyDef>,
+}
+
+impl LayoutFile {
+    pub fn from_toml(text: &str) -> Result<LayoutFile, KeyboardError> {
+        toml::from_str(text).map_err(|e| {
+            let (line, column) = location(text, e.span().map_or(0, |s| s.start));
+            KeyboardError::LayoutParsingError {
+                line,
+                column,
+                message: e.message().trim().to_string(),
+            }
+        })
+    }
+
+    // Everything that keeps the layout from being drawn or typed on unambiguously
+    pub fn problems(&self) -> Vec<KeyboardError> {
+        let mut problems = Vec::new();
+
+        for key in &self.keys {
+            // NaN fails every comparison, so it is checked first
+            let message = if !key.col.is_finite() || !key.width.is_finite() {
+                "the column and width must be numbers"
+            } else if key.col < 0.0 {
+                "the column can't be negative"
+            } else if key.width * UNIT < 1.0 {
+                "the width must be at least 0.25"
+            } else if key.height == 0 {
+                "the height must be at least 1"
+            } else {
+                continue;
+            };
+            problems.push(KeyboardError::InvalidGeometry {
+                row: key.row,
+                col: key.col,
+                message: message.to_string(),
+            });
+        }
+
+        // AltGr legends often repeat characters found elsewhere, e.g. '^' on AZERTY
+        let mut typed: Vec<(char, usize)> = Vec::new();
+        for (i, key) in self.keys.iter().enumerate() {
+            for c in [key.typed(), key.shift].into_iter().flatten() {
+                match typed.iter().find(|(t, _)| *t == c) {
+                    Some((_, first)) if *first != i => {
+                        let first = &self.keys[*first];
+                        problems.push(KeyboardError::DuplicateLegend {
+                            legend: c,
+                            first: (first.row, first.col),
+                            second: (key.row, key.col),
+                        });
+                    }
+                    Some(_) => {}
+                    None => typed.push((c, i)),
+                }
+            }
+        }
+
+        problems
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("layouts only hold serializable values")
+    }
+
+    // Draws the keys as boxes, one line for the legends of each row and one for the edges
+    // between rows. Keys covering several rows are drawn first so that the keys of the next
+    // rows can cut into them, like the lower part of an ISO Enter.
+    pub fn render(&self) -> (String, TouchMap) {
+        let rows = self
+            .keys
+            .iter()
+            .map(|k| k.row + k.height)
+            .max()
+            .unwrap_or(0);
+        let width = self.keys.iter().map(|k| k.span().1 + 1).max().unwrap_or(0);
+
+        let mut grid: Vec<Vec<(char, Option<usize>)>> =
+            vec![vec![(' ', None); width]; 2 * rows + 1];
+        for cell in grid[0].iter_mut().take(width.saturating_sub(1)).skip(1) {
+            cell.0 = '_';
+        }
+
+        let mut order: Vec<usize> = (0..self.keys.len()).collect();
+        order.sort_by_key(|&i| self.keys[i].row);
+
+        for i in order {
+            let key = &self.keys[i];
+            let (a, b) = key.span();
+            let bottom = 2 * (key.row + key.height);
+
+            for (y, line) in grid
+                .iter_mut()
+                .enumerate()
+                .take(bottom + 1)
+                .skip(2 * key.row + 1)
+            {
+                let fill = if y == bottom {
+                    ('-', None)
+                } else {
+                    (' ', Some(i))
+                };
+                for cell in &mut line[a + 1..b] {
+                    *cell = fill;
+                }
+                line[a] = ('|', None);
+                line[b] = ('|', None);
+            }
+
+            let inner = b - a - 1;
+            let label: Vec<char> = key.display().chars().take(inner).collect();
+            let start = a + 1 + (inner - label.len()).div_ceil(2);
+            for (x, c) in label.into_iter().enumerate() {
+                grid[2 * key.row + 1][start + x].0 = c;
+            }
+        }
+
+        let mut art = String::new();
+        let mut touch_map = TouchMap::new();
+        let mut offset = 0;
+        for (y, line) in grid.iter().enumerate() {
+            if y > 0 {
+                art.push('\n');
+                offset += 1;
+            }
+            let text: String = line.iter().map(|(c, _)| *c).collect();
+            let text = text.trim_end();
+            for (x, (_, key)) in line.iter().enumerate().take(text.chars().count()) {
+                if let Some(key) = key {
+                    touch_map.insert(offset + x, *key);
+                }
+            }
+            art.push_str(text);
+            offset += text.chars().count();
+        }
+
+        (art, touch_map)
+    }
+
+    // Reads the older format, a drawing and a mask of the same shape separated by a blank
+    // line, where each key is filled with its uppercase legend or a lowercase letter for the
+    // keys that can't stand for themselves.
+    pub fn from_legacy(name: &str, raw: &str) -> Result<LayoutFile, KeyboardError> {
+        let lines: Vec<&str> = raw.lines().collect();
+        let end = lines
+            .iter()
+            .rposition(|l| !l.trim().is_empty())
+            .map_or(0, |i| i + 1);
+        let blanks: Vec<usize> = (0..end).filter(|i| lines[*i].trim().is_empty()).collect();
+        let separator = match blanks[..] {
+            [blank] => blank,
+            [] => {
+                return Err(KeyboardError::LayoutParsingError {
+                    line: end + 1,
+                    column: 1,
+                    message: String::from(
+                        "expected the drawing and the key mapping separated by a blank line",
+                    ),
+                })
+            }
+            [_, blank, ..] => {
+                return Err(KeyboardError::LayoutParsingError {
+                    line: blank + 1,
+                    column: 1,
+                    message: String::from("expected a single blank line after the drawing"),
+                })
+            }
+        };
+
+        let drawing: Vec<Vec<char>> = lines[..separator]
+            .iter()
+            .map(|l| l.trim_end().chars().collect())
+            .collect();
+        let mask = &lines[separator + 1..end];
+
+        for n in 0..drawing.len().max(mask.len()) {
+            let drawing_width = drawing.get(n).map_or(0, |l| l.len());
+            let mask_width = mask.get(n).map_or(0, |l| l.trim_end().chars().count());
+            if drawing_width != mask_width {
+                return Err(KeyboardError::DimensionMismatch {
+                    line: separator + n + 2,
+                    drawing: drawing_width,
+                    mask: mask_width,
+                });
+            }
+        }
+
+        let mut keys: Vec<KeyDef> = Vec::new();
+        let mut row = 0;
+
+        for (n, line) in mask.iter().enumerate() {
+            let chars: Vec<char> = line.chars().collect();
+            let bars: Vec<usize> = (0..chars.len()).filter(|i| chars[*i] == '|').collect();
+            let mut found = false;
+
+            let outside = (0..chars.len()).find(|i| {
+                !" -_|".contains(chars[*i])
+                    && (bars.is_empty() || *i < bars[0] || *i > bars[bars.len() - 1])
+            });
+            if let Some(column) = outside {
+                return Err(KeyboardError::LayoutParsingError {
+                    line: separator + n + 2,
+                    column: column + 1,
+                    message: String::from("key not enclosed in '|'"),
+                });
+            }
+
+            for edges in bars.windows(2) {
+                let (a, b) = (edges[0], edges[1]);
+                let Some(mask) = chars[a + 1..b].iter().find(|c| !" -_".contains(**c)) else {
+                    continue;
+                };
+                found = true;
+
+                let (special, base) = match mask {
+                    'b' => (Some(Special::Backspace), None),
+                    't' => (Some(Special::Tab), None),
+                    'c' if a == 0 => (Some(Special::CapsLock), None),
+                    'c' | 'r' => (Some(Special::Ctrl), None),
+                    'e' => (Some(Special::Enter), None),
+                    's' => (Some(Special::Shift), None),
+                    'a' => (Some(Special::Alt), None),
+                    'p' => (Some(Special::Space), None),
+                    'm' => (None, Some('-')),
+                    'u' => (None, Some('_')),
+                    c => (None, Some(lowercase(*c))),
+                };
+
+                let key = KeyDef {
+                    row,
+                    col: a as f32 / UNIT,
+                    width: (b - a) as f32 / UNIT,
+                    height: 1,
+                    finger: None,
+                    hand: None,
+                    special,
+                    label: None,
+                    base,
+                    shift: base.map(uppercase).filter(|u| Some(*u) != base),
+                    altgr: None,
+                };
+
+                // The lower part of a key covering two rows
+                if let Some(upper) = keys.iter_mut().find(|k| {
+                    special.is_some()
+                        && k.special == special
+                        && k.row + 1 == row
+                        && k.span().0 < b
+                        && a < k.span().1
+                }) {
+                    upper.height = 2;
+                    continue;
+                }
+
+                let label = drawing.get(n).map(|l| {
+                    l.iter()
+                        .skip(a + 1)
+                        .take(b - a - 1)
+                        .collect::<String>()
+                        .replace('|', " ")
+                        .split_whitespace()
+                        .collect::<Vec<&str>>()
+                        .join(" ")
+                });
+                keys.push(KeyDef {
+                    label: label.filter(|l| special.is_some() && !l.is_empty()),
+                    ..key
+                });
+            }
+
+            if found {
+                row += 1;
+            }
+        }
+
+        assign_fingers(&mut keys);
+        Ok(LayoutFile {
+            name: name.to_string(),
+            keys,
+        })
+    }
+}
+
+// Line and column of a byte offset of the text, both starting at 1
+fn location(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    (line, column)
+}
+
+fn lowercase(c: char) -> char {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+fn uppercase(c: char) -> char {
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) => u,
+        _ => c,
+    }
+}
+
+// Standard touch typing fingers of the legend keys of the first four rows, in order
+const ROW_FINGERS: [&[Finger]; 4] = {
+    use Finger::*;
+    [
+        &[
+            LeftPinky,
+            LeftPinky,
+            LeftRing,
+            LeftMiddle,
+            LeftIndex,
+            LeftIndex,
+            RightIndex,
+            RightIndex,
+            RightMiddle,
+            RightRing,
+            RightPinky,
+            RightPinky,
+            RightPinky,
+        ],
+        &[
+            LeftPinky,
+            LeftRing,
+            LeftMiddle,
+            LeftIndex,
+            LeftIndex,
+            RightIndex,
+            RightIndex,
+            RightMiddle,
+            RightRing,
+            RightPinky,
+            RightPinky,
+            RightPinky,
+            RightPinky,
+        ],
+        &[
+            LeftPinky,
+            LeftRing,
+            LeftMiddle,
+            LeftIndex,
+            LeftIndex,
+            RightIndex,
+            RightIndex,
+            RightMiddle,
+            RightRing,
+            RightPinky,
+            RightPinky,
+            RightPinky,
+        ],
+        &[
+            LeftPinky,
+            LeftRing,
+            LeftMiddle,
+            LeftIndex,
+            LeftIndex,
+            RightIndex,
+            RightIndex,
+            RightMiddle,
+            RightRing,
+            RightPinky,
+        ],
+    ]
+};
+
+// Guesses the finger and hand of the keys that don't have one from their place on their row
+pub fn assign_fingers(keys: &mut [KeyDef]) {
+    let rows = keys.iter().map(|k| k.row + 1).max().unwrap_or(0);
+
+    for row in 0..rows {
+        let in_row: Vec<usize> = (0..keys.len()).filter(|i| keys[*i].row == row).collect();
+        let left = in_row.iter().map(|i| keys[*i].col).fold(f32::MAX, f32::min);
+        let right = in_row
+            .iter()
+            .map(|i| keys[*i].col + keys[*i].width)
+            .fold(f32::MIN, f32::max);
+        let middle = (left + right) / 2.0;
+
+        let legends: Vec<usize> = in_row
+            .iter()
+            .copied()
+            .filter(|i| keys[*i].base.is_some())
+            .collect();
+        let mut fingers: Vec<Finger> = ROW_FINGERS.get(row).map_or(Vec::new(), |f| f.to_vec());
+        // The extra key next to the left Shift of ISO keyboards
+        if row == 3 && legends.len() == fingers.len() + 1 {
+            fingers.insert(0, Finger::LeftPinky);
+        }
+
+        for i in in_row {
+            let key = &keys[i];
+            if key.finger.is_some() {
+                continue;
+            }
+            let on_left = key.col + key.width / 2.0 < middle;
+            let finger = match key.special {
+                Some(Special::Space) => Some(Finger::RightThumb),
+                Some(Special::Alt | Special::AltGr) if on_left => Some(Finger::LeftThumb),
+                Some(Special::Alt | Special::AltGr) => Some(Finger::RightThumb),
+                Some(_) if on_left => Some(Finger::LeftPinky),
+                Some(_) => Some(Finger::RightPinky),
+                None => legends
+                    .iter()
+                    .position(|l| *l == i)
+                    .and_then(|n| fingers.get(n).copied()),
+            };
+            keys[i].finger = finger;
+            keys[i].hand = keys[i].hand();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGACY: &str = " _________________
+| ` | 1 | 2 |  <- |
+|---|---|---|-----|
+|Shift   | Z |Spc |
+|--------|---|----|
+
+ _________________
+|```|111|222|bbbbb|
+|---|---|---|-----|
+|ssssssss| Z |pppp|
+|--------|---|----|";
+
+    #[test]
+    fn converts_legacy_layouts() {
+        let layout = LayoutFile::from_legacy("small", LEGACY).unwrap();
+        let keys = &layout.keys;
+
+        assert_eq!(keys.len(), 7);
+        assert_eq!(keys[0].base, Some('`'));
+        assert_eq!(keys[0].shift, None);
+        assert_eq!(keys[1].finger, Some(Finger::LeftPinky));
+        assert_eq!(keys[2].finger, Some(Finger::LeftRing));
+        assert_eq!(keys[3].special, Some(Special::Backspace));
+        assert_eq!(keys[3].label.as_deref(), Some("<-"));
+        assert_eq!(keys[3].width, 1.5);
+        assert_eq!(keys[3].hand, Some(Hand::Right));
+        assert_eq!(keys[4].special, Some(Special::Shift));
+        assert_eq!(keys[4].finger, Some(Finger::LeftPinky));
+        assert_eq!(keys[5].row, 1);
+        assert_eq!(keys[5].col, 2.25);
+        assert_eq!(keys[5].base, Some('z'));
+        assert_eq!(keys[5].shift, Some('Z'));
+        assert_eq!(keys[6].typed(), Some(' '));
+
+        // Survives a round trip through the TOML format
+        let reloaded = LayoutFile::from_toml(&layout.to_toml()).unwrap();
+        assert_eq!(&reloaded.keys, keys);
+
+        assert!(matches!(
+            LayoutFile::from_legacy("small", "no mask"),
+            Err(KeyboardError::LayoutParsingError { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn locates_errors() {
+        assert!(matches!(
+            LayoutFile::from_toml(
+                "name = \"x\"\n\n[[key]]\nrow = 0\ncol = 0\nfinger = \"thumb\"\n"
+            ),
+            Err(KeyboardError::LayoutParsingError {
+                line: 6,
+                column: 10,
+                ..
+            })
+        ));
+        assert!(matches!(
+            LayoutFile::from_legacy("small", &LEGACY.replacen("|Spc |", "|Spc|", 1)),
+            Err(KeyboardError::DimensionMismatch {
+                line: 10,
+                drawing: 18,
+                mask: 19
+            })
+        ));
+        assert!(matches!(
+            LayoutFile::from_legacy(
+                "small",
+                &LEGACY
+                    .replacen("<- |", "<- |x", 1)
+                    .replacen("|bbbbb|", "|bbbbb|x", 1)
+            ),
+            Err(KeyboardError::LayoutParsingError {
+                line: 8,
+                column: 20,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn finds_duplicates_and_bad_geometry() {
+        let mut layout = LayoutFile::from_legacy("small", LEGACY).unwrap();
+        assert!(layout.problems().is_empty());
+
+        layout.keys[2].base = Some('`');
+        layout.keys[6].width = 0.0;
+        let problems = layout.problems();
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(
+            problems[0],
+            KeyboardError::InvalidGeometry { row: 1, .. }
+        ));
+        assert!(matches!(
+            problems[1],
+            KeyboardError::DuplicateLegend {
+                legend: '`',
+                first: (0, _),
+                second: (0, _)
+            }
+        ));
+
+        let mut layout = LayoutFile::from_legacy("small", LEGACY).unwrap();
+        layout.keys[0].width = f32::NAN;
+        layout.keys[1].col = f32::INFINITY;
+        assert_eq!(layout.problems().len(), 2);
+        let layout = LayoutFile::from_toml(
+            "name = \"x\"\n\n[[key]]\nrow = 0\ncol = 0\nwidth = nan\nfinger = \"left_index\"\n",
+        )
+        .unwrap();
+        assert_eq!(layout.problems().len(), 1);
+    }
+
+    #[test]
+    fn renders_keys_as_boxes() {
+        let layout = LayoutFile::from_toml(
+            r#"
+            name = "tiny"
+
+            [[key]]
+            row = 0
+            col = 0
+            base = "q"
+
+            [[key]]
+            row = 0
+            col = 1
+            width = 1.5
+            height = 2
+            special = "enter"
+
+            [[key]]
+            row = 1
+            col = 0
+            base = "a"
+            "#,
+        )
+        .unwrap();
+        let (art, touch_map) = layout.render();
+
+        assert_eq!(
+            art,
+            [
+                " _________",
+                "| Q |Enter|",
+                "|---|     |",
+                "| A |     |",
+                "|---|-----|",
+            ]
+            .join("\n")
+        );
+        // The middle of the Q, then the inside of Enter on the second row
+        assert_eq!(touch_map.get(&13), Some(&0));
+        assert_eq!(touch_map.get(&40), Some(&1));
+        assert_eq!(touch_map.get(&23), None);
+    }
+}
@@ -1,64 +1,215 @@
 #![feature(test)]
 
 use crossterm::{
-    event::{self, KeyCode},
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    event::{
+        self, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    terminal::{
+        disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
     ExecutableCommand,
 };
 use ratatui::{prelude::CrosstermBackend, Terminal};
 use std::io::{stderr, Result};
-use ui::Component;
+use std::rc::Rc;
 
 mod state;
-use state::{Action, State};
+use state::State;
 
+mod adaptive;
+mod bindings;
+use bindings::Bindings;
 mod cli;
+mod clock;
+use clock::{Clock, MonotonicClock};
+mod curriculum;
+mod fingers;
+mod heatmap;
+mod history;
+use history::History;
 mod keyboard;
-use keyboard::Keyboard;
+use keyboard::{Emulation, Keyboard, KeyboardError, LayoutSource};
+mod layout;
+mod playlist;
+mod report;
+mod source;
+mod timing;
 mod ui;
 mod utils;
+mod words;
 
-fn main() -> Result<()> {
-    const KEYBOARD_LAYOUT: &str = include_str!("../resources/layout.txt");
-    let target = cli::main();
-    let keyboard = Keyboard::new(KEYBOARD_LAYOUT.to_string()).unwrap();
-    let mut state = State::new(keyboard);
-    state.dispatch(Action::SetTarget(target.unwrap()));
+// With emulation the on-screen keyboard is the emulated layout
+fn load_keyboard(
+    practice: &cli::Practice,
+) -> std::result::Result<(Keyboard, Option<Emulation>), KeyboardError> {
+    let physical = practice.layout.load()?;
+    match &practice.emulate {
+        Some(name) => {
+            let target = LayoutSource::Builtin(name.clone()).load()?;
+            let emulation = Emulation::new(&physical, &target)?;
+            Ok((target, Some(emulation)))
+        }
+        None => Ok((physical, None)),
+    }
+}
+
+fn layout_name(practice: &cli::Practice) -> String {
+    let name = match &practice.layout {
+        LayoutSource::Builtin(name) | LayoutSource::File(name) => name.clone(),
+    };
+    match &practice.emulate {
+        Some(emulated) => format!("{} typed on {}", emulated, name),
+        None => name,
+    }
+}
+
+fn check_layout(layout: &LayoutSource, text_path: Option<&str>) {
+    let text = text_path.map(|path| match utils::read_file_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    });
+    let name = match layout {
+        LayoutSource::Builtin(name) | LayoutSource::File(name) => name,
+    };
 
-    // println!("{:?}", state.keyboard.touch_map);
-    // return Ok(());
+    let problems = keyboard::check(layout, text.as_deref());
+    if problems.is_empty() {
+        println!("{}: ok", name);
+        return;
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    std::process::exit(1);
+}
+
+fn main() -> Result<()> {
+    let Some(command) = cli::main() else {
+        std::process::exit(2);
+    };
+    let practice = match command {
+        cli::CliCommand::Practice(practice) => practice,
+        cli::CliCommand::Stats(query) => {
+            if let Err(e) = report::run(query, &History::open_default()) {
+                eprintln!("Could not read history: {}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        cli::CliCommand::Layout(cli::LayoutCommand::Convert(source)) => {
+            match source.load_file() {
+                Ok(layout) => print!("{}", layout.to_toml()),
+                Err(e) => {
+                    eprintln!("Invalid keyboard layout: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return Ok(());
+        }
+        cli::CliCommand::Layout(cli::LayoutCommand::Check { layout, text }) => {
+            check_layout(&layout, text.as_deref());
+            return Ok(());
+        }
+    };
+    let bindings = match Bindings::open_default() {
+        Ok(bindings) => bindings,
+        Err(e) => {
+            eprintln!("Invalid key bindings: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let (keyboard, emulation) = match load_keyboard(&practice) {
+        Ok(keyboard) => keyboard,
+        Err(e) => {
+            eprintln!("Invalid keyboard layout: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let clock = Rc::new(MonotonicClock::new());
+    let mut state = State::new(keyboard, History::open_default(), clock.clone());
+    state.emulation = emulation;
+    state.home.layout = layout_name(&practice);
+    state.heatmap = practice.heatmap;
+    if let Some(heatmap) = state
+        .heatmap
+        .as_mut()
+        .filter(|h| h.scope == heatmap::HeatmapScope::History)
+    {
+        match state.history.load() {
+            Ok(sessions) => heatmap.past = heatmap::history_samples(&sessions),
+            Err(e) => {
+                eprintln!("Could not read history: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    state.type_test.mode = practice.mode;
+    state.type_test.alignment = practice.alignment;
+    state.type_test.correction = practice.correction;
+    state.type_test.keyboard.guide = practice.guide;
+    state.go_home();
+    if let Some(mut source) = practice.source {
+        match &mut source {
+            source::TextSource::Curriculum(options) => {
+                options.layout = state.type_test.keyboard.name.clone();
+                options.order = curriculum::letter_order(&state.type_test.keyboard);
+            }
+            source::TextSource::Playlist(playlist) => {
+                if let Err(e) = playlist.collect() {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
+            _ => {}
+        }
+        let target = match source.load() {
+            Ok(target) => target,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        state.start(source, target);
+    }
 
     stderr().execute(EnterAlternateScreen)?;
     enable_raw_mode()?;
+    // Key releases time how long keys are held, on the terminals that report them
+    let enhanced = supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        // Without REPORT_ALL_KEYS_AS_ESCAPE_CODES, terminals send letters as plain text and never
+        // report their releases
+        stderr().execute(PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS,
+        ))?;
+    }
     let mut terminal = Terminal::new(CrosstermBackend::new(stderr()))?;
     terminal.clear()?;
 
-    let mut type_test_view_component = ui::TypeTestView::new();
+    let mut router = ui::Router::new();
 
-    loop {
-        state.type_test.keyboard.tick();
+    while !state.should_quit {
+        state.tick();
 
-        terminal.draw(|frame| {
-            if let state::View::TypeTest = state.current_view {
-                type_test_view_component.render(&state, frame, frame.size())
-            }
-        })?;
+        terminal.draw(|frame| router.render(&state, frame))?;
 
         if event::poll(std::time::Duration::from_millis(100))? {
             if let event::Event::Key(key) = event::read()? {
-                match (key.modifiers, key.code) {
-                    (event::KeyModifiers::CONTROL, _) => break, // This will catch Control + C and break out of the loop
-                    (_, KeyCode::Char(k)) => {
-                        state.dispatch(Action::Char(k));
-                    }
-                    (_, KeyCode::Backspace) => state.dispatch(Action::Backspace), // Check for backspace and dispatch the action
-                    (_, KeyCode::Enter) => state.dispatch(Action::Char('\n')), // Check for backspace and dispatch the action
-                    _ => {} // For all other keys, do nothing
-                }
+                state.handle_key(key, &bindings, clock.now());
             }
         }
     }
 
+    if enhanced {
+        stderr().execute(PopKeyboardEnhancementFlags)?;
+    }
     stderr().execute(LeaveAlternateScreen)?;
     disable_raw_mode()?;
     Ok(())
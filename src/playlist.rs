@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+
+use crate::state::StateStats;
+use crate::utils::{canonical_source, read_file_to_string};
+
+// Results of one file of a playlist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub source: String,
+    pub elapsed: u128,
+    pub words_per_minute: Option<f32>,
+    pub precision: Option<f32>,
+    // Keystrokes and the correct ones, the precision is derived from
+    pub total: i32,
+    pub valid: i32,
+}
+
+impl Segment {
+    pub fn new(source: &str, stats: &StateStats) -> Segment {
+        let (total, valid) = stats
+            .keys_stats
+            .values()
+            .fold((0, 0), |(total, valid), (t, v)| (total + t, valid + v));
+        Segment {
+            source: source.to_string(),
+            elapsed: stats.elapsed,
+            words_per_minute: stats.words_per_minute,
+            precision: stats.precision,
+            total,
+            valid,
+        }
+    }
+}
+
+// The segments of a playlist taken together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub elapsed: u128,
+    // Weighted by the time spent on each segment
+    pub words_per_minute: Option<f32>,
+    pub precision: Option<f32>,
+}
+
+// Files typed one after the other, each of them a test of its own. Directories are walked
+// for their files, skipping the hidden ones and the ones a `.gitignore` excludes.
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    pub paths: Vec<String>,
+    // Gitignore style, e.g. "*.rs" or "!*_test.rs", they only filter the files of directories
+    pub globs: Vec<String>,
+    // Found by `collect`, canonical
+    pub files: Vec<String>,
+    pub current: usize,
+    // Of the files typed so far, in order
+    pub segments: Vec<Segment>,
+    // Why the files left out during the session could not be loaded
+    pub skipped: Vec<String>,
+}
+
+impl Playlist {
+    pub fn new(paths: Vec<String>, globs: Vec<String>) -> Playlist {
+        Playlist {
+            paths,
+            globs,
+            files: Vec::new(),
+            current: 0,
+            segments: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+
+    // Empty files and the ones that aren't UTF-8 text are left out, they could not be typed
+    pub fn collect(&mut self) -> Result<(), String> {
+        self.files.clear();
+        let mut skipped = 0;
+        for path in &self.paths {
+            let found = if Path::new(path).is_dir() {
+                walk(path, &self.globs)?
+            } else if Path::new(path).is_file() {
+                vec![canonical_source(path)]
+            } else {
+                return Err(format!("{}: no such file or directory", path));
+            };
+            for file in found {
+                if self.files.contains(&file) {
+                    continue;
+                }
+                match read_file_to_string(&file) {
+                    Ok(text) if !text.trim().is_empty() => self.files.push(file),
+                    _ => skipped += 1,
+                }
+            }
+        }
+
+        if self.files.is_empty() {
+            return Err(format!(
+                "No file to practice in {}, {} empty or binary files skipped",
+                self.paths.join(", "),
+                skipped
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn current_file(&self) -> Option<&String> {
+        self.files.get(self.current)
+    }
+
+    // Typing a segment again replaces its results
+    pub fn record(&mut self, segment: Segment) {
+        self.segments.truncate(self.current);
+        self.segments.push(segment);
+    }
+
+    pub fn next(&self) -> Option<Playlist> {
+        (self.current + 1 < self.files.len()).then(|| Playlist {
+            current: self.current + 1,
+            ..self.clone()
+        })
+    }
+
+    // Leaves out the current file, which could not be loaded. Returns whether a file follows
+    // it, otherwise the playlist ends on the previous one.
+    pub fn skip(&mut self, error: String) -> bool {
+        self.files.remove(self.current);
+        self.skipped.push(error);
+        if self.current < self.files.len() {
+            return true;
+        }
+        self.current = self.current.saturating_sub(1);
+        false
+    }
+
+    // Once every file has been typed
+    pub fn summary(&self) -> Option<Summary> {
+        if self.files.is_empty() || self.segments.len() < self.files.len() {
+            return None;
+        }
+
+        let elapsed = self.segments.iter().map(|s| s.elapsed).sum();
+        let timed: Vec<(f32, u128)> = self
+            .segments
+            .iter()
+            .filter_map(|s| s.words_per_minute.map(|wpm| (wpm, s.elapsed)))
+            .collect();
+        let timed_elapsed: u128 = timed.iter().map(|(_, elapsed)| elapsed).sum();
+        let words_per_minute = (timed_elapsed > 0).then(|| {
+            timed
+                .iter()
+                .map(|(wpm, elapsed)| wpm * *elapsed as f32)
+                .sum::<f32>()
+                / timed_elapsed as f32
+        });
+        let (total, valid) = self.segments.iter().fold((0, 0), |(total, valid), s| {
+            (total + s.total, valid + s.valid)
+        });
+
+        Some(Summary {
+            elapsed,
+            words_per_minute,
+            precision: (total > 0).then(|| valid as f32 / total as f32),
+        })
+    }
+}
+
+// The files of a directory, sorted by path
+fn walk(dir: &str, globs: &[String]) -> Result<Vec<String>, String> {
+    let mut overrides = OverrideBuilder::new(dir);
+    for glob in globs {
+        overrides.add(glob).map_err(|e| e.to_string())?;
+    }
+    let overrides = overrides.build().map_err(|e| e.to_string())?;
+
+    let mut files = Vec::new();
+    let walker = WalkBuilder::new(dir)
+        .overrides(overrides)
+        // Curated folders are not always repositories
+        .require_git(false)
+        // The globs would let hidden files through otherwise
+        .filter_entry(|e| e.depth() == 0 || !e.file_name().to_string_lossy().starts_with('.'))
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .build();
+    for entry in walker {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            files.push(canonical_source(&entry.path().display().to_string()));
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(elapsed: u128, words_per_minute: f32, total: i32, valid: i32) -> Segment {
+        Segment {
+            source: String::new(),
+            elapsed,
+            words_per_minute: Some(words_per_minute),
+            precision: Some(valid as f32 / total as f32),
+            total,
+            valid,
+        }
+    }
+
+    #[test]
+    fn collects_files() {
+        let dir = std::env::temp_dir().join(format!("blitzkey-playlist-{}", std::process::id()));
+        for sub in ["build", "sub"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in ["a.rs", "b.txt", "build/c.rs", "sub/d.rs", ".e.rs"] {
+            std::fs::write(dir.join(file), "text").unwrap();
+        }
+        std::fs::write(dir.join("empty.txt"), " \n").unwrap();
+        std::fs::write(dir.join("sub/image.png"), [0x89, 0x50, 0xff, 0xfe]).unwrap();
+        std::fs::write(dir.join(".gitignore"), "build/\n").unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let path = |file: &str| dir.join(file).display().to_string();
+
+        let mut playlist = Playlist::new(vec![path("")], Vec::new());
+        playlist.collect().unwrap();
+        assert_eq!(
+            playlist.files,
+            vec![path("a.rs"), path("b.txt"), path("sub/d.rs")]
+        );
+
+        // Files named on their own are kept whatever the globs
+        let mut playlist = Playlist::new(
+            vec![path("build/c.rs"), path(""), path("b.txt")],
+            vec![String::from("*.rs")],
+        );
+        playlist.collect().unwrap();
+        assert_eq!(
+            playlist.files,
+            vec![
+                path("build/c.rs"),
+                path("a.rs"),
+                path("sub/d.rs"),
+                path("b.txt")
+            ]
+        );
+
+        let mut playlist = Playlist::new(vec![path("missing")], Vec::new());
+        assert!(playlist.collect().is_err());
+        let mut playlist =
+            Playlist::new(vec![path("empty.txt"), path("sub/image.png")], Vec::new());
+        assert!(playlist.collect().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sums_up_segments() {
+        let mut playlist = Playlist::new(Vec::new(), Vec::new());
+        playlist.files = vec![String::from("a"), String::from("b")];
+
+        playlist.record(segment(1000, 90.0, 10, 5));
+        assert_eq!(playlist.summary(), None);
+        // Retried
+        playlist.record(segment(1000, 30.0, 10, 10));
+
+        let mut playlist = playlist.next().unwrap();
+        assert_eq!(playlist.current_file(), Some(&String::from("b")));
+        assert!(playlist.next().is_none());
+        playlist.record(segment(3000, 50.0, 30, 27));
+
+        assert_eq!(
+            playlist.summary(),
+            Some(Summary {
+                elapsed: 4000,
+                words_per_minute: Some(45.0),
+                precision: Some(37.0 / 40.0),
+            })
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Datelike, Local, NaiveDate, TimeZone};
+use serde::Serialize;
+
+use crate::history::{History, HistoryError, SessionRecord};
+use crate::state::{
+    keys_precision, merge_ngrams, most_error_prone, slowest, KeysStats, NgramStats,
+};
+
+// N-grams listed in each section of the table
+const NGRAM_ROWS: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Daily,
+    Weekly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+pub struct StatsQuery {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub source: Option<String>,
+    pub mode: Option<String>,
+    pub correction: Option<String>,
+    pub period: Period,
+    pub format: Format,
+    pub keys: bool,
+    pub ngrams: bool,
+}
+
+#[derive(Serialize)]
+pub struct Aggregate {
+    pub best: Option<f32>,
+    pub median: Option<f32>,
+    pub last: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct PeriodStats {
+    pub period: String,
+    pub sessions: usize,
+    pub words_per_minute: Option<f32>,
+    pub precision: Option<f32>,
+    pub best_words_per_minute: Option<f32>,
+}
+
+#[derive(Serialize)]
+pub struct KeyPeriod {
+    pub period: String,
+    pub total: i32,
+    pub precision: f32,
+}
+
+#[derive(Serialize)]
+pub struct KeyTrend {
+    pub key: char,
+    pub total: i32,
+    pub precision: f32,
+    pub periods: Vec<KeyPeriod>,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub sessions: usize,
+    pub words_per_minute: Aggregate,
+    pub precision: Aggregate,
+    pub periods: Vec<PeriodStats>,
+    pub keys: Vec<KeyTrend>,
+    // Summed over the sessions, slowest first
+    pub ngrams: Vec<NgramStats>,
+}
+
+fn session_day(session: &SessionRecord) -> NaiveDate {
+    Local
+        .timestamp_millis_opt(session.started_at as i64)
+        .single()
+        .map_or(NaiveDate::default(), |d| d.date_naive())
+}
+
+fn period_key(day: NaiveDate, period: Period) -> String {
+    match period {
+        Period::Daily => day.format("%Y-%m-%d").to_string(),
+        Period::Weekly => {
+            let week = day.iso_week();
+            format!("{}-W{:02}", week.year(), week.week())
+        }
+    }
+}
+
+fn mean(values: &[f32]) -> Option<f32> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f32>() / values.len() as f32)
+    }
+}
+
+fn aggregate(values: &[f32]) -> Aggregate {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+
+    let median = match sorted.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(sorted[n / 2]),
+        n => Some((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+    };
+
+    Aggregate {
+        best: sorted.last().copied(),
+        median,
+        last: values.last().copied(),
+    }
+}
+
+fn merge_keys_stats(into: &mut KeysStats, session: &SessionRecord) {
+    for (key, total, valid) in &session.keys_stats {
+        let entry = into.entry(*key).or_insert((0, 0));
+        entry.0 += total;
+        entry.1 += valid;
+    }
+}
+
+impl StatsQuery {
+    fn matches(&self, session: &SessionRecord) -> bool {
+        let day = session_day(session);
+
+        self.from.is_none_or(|from| day >= from)
+            && self.to.is_none_or(|to| day <= to)
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| session.source.contains(source.as_str()))
+            && self
+                .mode
+                .as_ref()
+                .is_none_or(|mode| session.mode.to_string() == *mode || session.mode.name() == mode)
+            && self
+                .correction
+                .as_ref()
+                .is_none_or(|correction| session.correction.name() == correction)
+    }
+
+    pub fn report(&self, sessions: &[SessionRecord]) -> Report {
+        let mut sessions: Vec<&SessionRecord> =
+            sessions.iter().filter(|s| self.matches(s)).collect();
+        sessions.sort_by_key(|s| s.started_at);
+
+        let wpm: Vec<f32> = sessions.iter().filter_map(|s| s.words_per_minute).collect();
+        let precision: Vec<f32> = sessions.iter().filter_map(|s| s.precision).collect();
+
+        let mut by_period: BTreeMap<String, Vec<&SessionRecord>> = BTreeMap::new();
+        for session in &sessions {
+            by_period
+                .entry(period_key(session_day(session), self.period))
+                .or_default()
+                .push(session);
+        }
+
+        let periods = by_period
+            .iter()
+            .map(|(period, sessions)| {
+                let wpm: Vec<f32> = sessions.iter().filter_map(|s| s.words_per_minute).collect();
+                let precision: Vec<f32> = sessions.iter().filter_map(|s| s.precision).collect();
+                PeriodStats {
+                    period: period.clone(),
+                    sessions: sessions.len(),
+                    words_per_minute: mean(&wpm),
+                    precision: mean(&precision),
+                    best_words_per_minute: aggregate(&wpm).best,
+                }
+            })
+            .collect();
+
+        // Per-key precision goes through the same calculation as a live session, on summed counts
+        let mut overall: KeysStats = HashMap::new();
+        let mut per_period: Vec<(String, KeysStats)> = Vec::new();
+        for (period, sessions) in &by_period {
+            let mut keys_stats = HashMap::new();
+            for session in sessions {
+                merge_keys_stats(&mut keys_stats, session);
+                merge_keys_stats(&mut overall, session);
+            }
+            per_period.push((period.clone(), keys_stats));
+        }
+
+        let keys = keys_precision(&overall)
+            .into_iter()
+            .map(|(key, precision)| KeyTrend {
+                key,
+                total: overall[&key].0,
+                precision,
+                periods: per_period
+                    .iter()
+                    .filter_map(|(period, keys_stats)| {
+                        // Hand-edited or older lines can hold keys that were never typed
+                        let (total, valid) = keys_stats.get(&key)?;
+                        (*total > 0).then(|| KeyPeriod {
+                            period: period.clone(),
+                            total: *total,
+                            precision: *valid as f32 / *total as f32,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut ngrams = Vec::new();
+        for session in &sessions {
+            merge_ngrams(&mut ngrams, &session.ngrams);
+        }
+        ngrams.sort_by(|a, b| {
+            let latency = |s: &NgramStats| s.mean_latency().unwrap_or(0.0);
+            latency(b)
+                .total_cmp(&latency(a))
+                .then(a.ngram.cmp(&b.ngram))
+        });
+
+        Report {
+            sessions: sessions.len(),
+            words_per_minute: aggregate(&wpm),
+            precision: aggregate(&precision),
+            periods,
+            keys,
+            ngrams,
+        }
+    }
+}
+
+fn display_key(key: char) -> String {
+    match key {
+        '\n' => String::from("↵"),
+        ' ' => String::from("␣"),
+        '\t' => String::from("⇥"),
+        c => c.to_string(),
+    }
+}
+
+fn opt(value: Option<f32>, scale: f32) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.1}", v * scale))
+}
+
+fn print_ngrams(title: &str, ngrams: &[NgramStats]) {
+    println!("{}", title);
+    println!(
+        "{:<8} {:>8} {:>8} {:>12}",
+        "n-gram", "typed", "errors", "latency ms"
+    );
+    for stats in ngrams {
+        println!(
+            "{:<8} {:>8} {:>8} {:>12}",
+            stats.ngram,
+            stats.count,
+            stats.errors,
+            opt(stats.mean_latency(), 1.0)
+        );
+    }
+}
+
+fn print_table(report: &Report, query: &StatsQuery) {
+    println!("Sessions: {}", report.sessions);
+    println!();
+    println!("{:<10} {:>8} {:>8} {:>8}", "", "best", "median", "last");
+    for (label, aggregate, scale) in [
+        ("WPM", &report.words_per_minute, 1.0),
+        ("Accuracy", &report.precision, 100.0),
+    ] {
+        println!(
+            "{:<10} {:>8} {:>8} {:>8}",
+            label,
+            opt(aggregate.best, scale),
+            opt(aggregate.median, scale),
+            opt(aggregate.last, scale)
+        );
+    }
+    println!();
+
+    if query.ngrams {
+        print_ngrams("Slowest", &slowest(&report.ngrams, NGRAM_ROWS));
+        println!();
+        print_ngrams("Most errors", &most_error_prone(&report.ngrams, NGRAM_ROWS));
+    } else if query.keys {
+        println!(
+            "{:<5} {:>8} {:>10} {:>10} {:>10}",
+            "key", "typed", "accuracy", "first", "last"
+        );
+        for key in &report.keys {
+            println!(
+                "{:<5} {:>8} {:>10.1} {:>10} {:>10}",
+                display_key(key.key),
+                key.total,
+                key.precision * 100.0,
+                opt(key.periods.first().map(|p| p.precision), 100.0),
+                opt(key.periods.last().map(|p| p.precision), 100.0)
+            );
+        }
+    } else {
+        println!(
+            "{:<10} {:>8} {:>8} {:>10} {:>10}",
+            "period", "sessions", "wpm", "best wpm", "accuracy"
+        );
+        for period in &report.periods {
+            println!(
+                "{:<10} {:>8} {:>8} {:>10} {:>10}",
+                period.period,
+                period.sessions,
+                opt(period.words_per_minute, 1.0),
+                opt(period.best_words_per_minute, 1.0),
+                opt(period.precision, 100.0)
+            );
+        }
+    }
+}
+
+fn csv_opt(value: Option<f32>) -> String {
+    value.map_or(String::new(), |v| v.to_string())
+}
+
+fn print_csv(report: &Report, query: &StatsQuery) {
+    if query.ngrams {
+        println!("ngram,count,errors,mean_latency,error_rate");
+        for stats in &report.ngrams {
+            println!(
+                "\"{}\",{},{},{},{}",
+                stats.ngram.replace('"', "\"\""),
+                stats.count,
+                stats.errors,
+                csv_opt(stats.mean_latency()),
+                stats.error_rate()
+            );
+        }
+    } else if query.keys {
+        println!("period,key,total,precision");
+        for key in &report.keys {
+            for period in &key.periods {
+                println!(
+                    "{},\"{}\",{},{}",
+                    period.period,
+                    display_key(key.key).replace('"', "\"\""),
+                    period.total,
+                    period.precision
+                );
+            }
+        }
+    } else {
+        println!("period,sessions,words_per_minute,best_words_per_minute,precision");
+        for period in &report.periods {
+            println!(
+                "{},{},{},{},{}",
+                period.period,
+                period.sessions,
+                csv_opt(period.words_per_minute),
+                csv_opt(period.best_words_per_minute),
+                csv_opt(period.precision)
+            );
+        }
+    }
+}
+
+pub fn run(query: StatsQuery, history: &History) -> Result<(), HistoryError> {
+    let (sessions, skipped) = history.load_with_skipped()?;
+    if skipped > 0 {
+        eprintln!(
+            "Warning: skipped {} unreadable lines of the history",
+            skipped
+        );
+    }
+    let report = query.report(&sessions);
+
+    match query.format {
+        Format::Table => print_table(&report, &query),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        Format::Csv => print_csv(&report, &query),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AlignmentMode, CorrectionPolicy, TestMode};
+
+    fn session(day: u32, wpm: f32, keys_stats: Vec<(char, i32, i32)>) -> SessionRecord {
+        let started_at = Local
+            .with_ymd_and_hms(2024, 3, day, 12, 0, 0)
+            .unwrap()
+            .timestamp_millis() as u64;
+        SessionRecord {
+            source: format!("/tmp/day{}.txt", day),
+            mode: TestMode::Full,
+            alignment: AlignmentMode::Strict,
+            correction: CorrectionPolicy::Free,
+            started_at,
+            ended_at: started_at + 60000,
+            words_per_minute: Some(wpm),
+            precision: Some(0.9),
+            keys_precision: Vec::new(),
+            keys_stats,
+            actions: Vec::new(),
+            ngrams: Vec::new(),
+        }
+    }
+
+    fn query() -> StatsQuery {
+        StatsQuery {
+            from: None,
+            to: None,
+            source: None,
+            mode: None,
+            correction: None,
+            period: Period::Daily,
+            format: Format::Table,
+            keys: false,
+            ngrams: false,
+        }
+    }
+
+    #[test]
+    fn aggregates_by_period() {
+        let sessions = vec![
+            session(4, 40.0, vec![('a', 2, 1)]),
+            session(4, 60.0, vec![('a', 2, 2)]),
+            session(11, 50.0, vec![('a', 4, 4), ('b', 1, 0)]),
+        ];
+
+        let report = query().report(&sessions);
+        assert_eq!(report.sessions, 3);
+        assert_eq!(report.words_per_minute.best, Some(60.0));
+        assert_eq!(report.words_per_minute.median, Some(50.0));
+        assert_eq!(report.words_per_minute.last, Some(50.0));
+        assert_eq!(report.periods.len(), 2);
+        assert_eq!(report.periods[0].words_per_minute, Some(50.0));
+
+        let a = &report.keys[0];
+        assert_eq!((a.key, a.total), ('a', 8));
+        assert_eq!(a.periods[0].precision, 0.75);
+        assert_eq!(a.periods[1].precision, 1.0);
+
+        let weekly = StatsQuery {
+            period: Period::Weekly,
+            ..query()
+        }
+        .report(&sessions);
+        assert_eq!(weekly.periods.len(), 2);
+    }
+
+    #[test]
+    fn skips_keys_never_typed() {
+        let sessions = vec![
+            session(4, 40.0, vec![('a', 2, 1), ('c', 0, 0)]),
+            session(11, 50.0, vec![('c', 2, 1)]),
+        ];
+
+        let report = query().report(&sessions);
+        let c = report.keys.iter().find(|k| k.key == 'c').unwrap();
+        assert_eq!(c.periods.len(), 1);
+        assert_eq!(c.periods[0].precision, 0.5);
+    }
+
+    #[test]
+    fn filters_sessions() {
+        let sessions = vec![
+            session(4, 40.0, Vec::new()),
+            session(5, 60.0, Vec::new()),
+            session(11, 50.0, Vec::new()),
+        ];
+
+        let report = StatsQuery {
+            from: NaiveDate::from_ymd_opt(2024, 3, 5),
+            ..query()
+        }
+        .report(&sessions);
+        assert_eq!(report.sessions, 2);
+
+        let report = StatsQuery {
+            source: Some(String::from("day4")),
+            ..query()
+        }
+        .report(&sessions);
+        assert_eq!(report.sessions, 1);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::adaptive::AdaptiveOptions;
+use crate::curriculum::CurriculumOptions;
+use crate::playlist::Playlist;
+use std::io::{self, IsTerminal, Read};
+
+use crate::utils::{read_file_to_string, strip_formatting};
+use crate::words::WordsOptions;
+
+fn load_file(path: &str) -> Result<String, String> {
+    match read_file_to_string(path) {
+        Ok(target) if target.trim().is_empty() => Err(String::from("The file is empty")),
+        Ok(target) => Ok(target),
+        Err(e) => Err(format!("{}: {}", path, e)),
+    }
+}
+
+// Where the text of a test comes from
+#[derive(Debug, Clone)]
+pub enum TextSource {
+    File(String),
+    // Piped in, it can only be read once
+    Stdin,
+    // Several files, one test each
+    Playlist(Playlist),
+    Words(WordsOptions),
+    Adaptive(AdaptiveOptions),
+    Curriculum(CurriculumOptions),
+}
+
+impl TextSource {
+    pub fn load(&self) -> Result<String, String> {
+        match self {
+            TextSource::File(path) => load_file(path),
+            TextSource::Playlist(playlist) => match playlist.current_file() {
+                Some(path) => load_file(path),
+                None => Err(String::from("No file to practice")),
+            },
+            TextSource::Stdin => {
+                let mut stdin = io::stdin();
+                if stdin.is_terminal() {
+                    return Err(String::from("Nothing was piped to stdin"));
+                }
+                let mut target = String::new();
+                match stdin.read_to_string(&mut target) {
+                    Ok(_) if target.trim().is_empty() => Err(String::from("stdin is empty")),
+                    Ok(_) => Ok(strip_formatting(&target)),
+                    Err(e) => Err(format!("stdin: {}", e)),
+                }
+            }
+            TextSource::Words(options) => options
+                .generate()
+                .ok_or_else(|| format!("Unknown word list {}", options.list)),
+            TextSource::Adaptive(options) => options.generate(),
+            TextSource::Curriculum(options) => options.generate(),
+        }
+    }
+
+    // Recorded in the history so a session can be traced back to its text
+    pub fn describe(&self) -> String {
+        match self {
+            TextSource::File(path) => path.clone(),
+            TextSource::Stdin => String::from("stdin"),
+            // Each file is recorded on its own
+            TextSource::Playlist(playlist) => playlist.current_file().cloned().unwrap_or_default(),
+            TextSource::Words(options) => options.describe(),
+            TextSource::Adaptive(options) => options.describe(),
+            TextSource::Curriculum(options) => options.describe(),
+        }
+    }
+
+    // A different text from the same source, if it can produce one
+    pub fn next(&self) -> Option<TextSource> {
+        match self {
+            TextSource::File(_) | TextSource::Stdin => None,
+            TextSource::Playlist(playlist) => playlist.next().map(TextSource::Playlist),
+            TextSource::Words(options) => Some(TextSource::Words(options.reseeded())),
+            TextSource::Adaptive(options) => Some(TextSource::Adaptive(options.reseeded())),
+            TextSource::Curriculum(options) => Some(TextSource::Curriculum(options.reseeded())),
+        }
+    }
+}
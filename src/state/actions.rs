@@ -1,12 +1,33 @@
-#[derive(Debug, Clone)]
+use serde::{Deserialize, Serialize};
+
+// Keystrokes further apart than this are pauses rather than typing
+pub const IDLE_LATENCY: u128 = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Action {
     Char(char),
     Backspace,
     // Enter,
     SetTarget(String),
+    // Back to the start of the word, or of the previous one when already there
+    DeleteWord,
+    // Back to the start of the line
+    ClearLine,
+    Restart,
+    Pause,
+    // Asks for a confirmation first
+    Quit,
+    // Arrow keys, to move through menus
+    Up,
+    Down,
+    Left,
+    Right,
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimedAction {
     pub action: Action,
     pub time: u128,
+    // When the key of a typed character was let go, if the terminal reports releases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub released: Option<u128>,
 }
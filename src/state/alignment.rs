@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// How typed characters are matched against the target text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlignmentMode {
+    // Position by position, a skipped or extra character shifts the rest of the word
+    #[default]
+    Strict,
+    // Each completed word is aligned with its target by edit distance
+    EditDistance,
+}
+
+impl fmt::Display for AlignmentMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlignmentMode::Strict => write!(f, "strict"),
+            AlignmentMode::EditDistance => write!(f, "edit-distance"),
+        }
+    }
+}
+
+// What happened to a target character once it has been typed or skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mark {
+    Correct,
+    Substitution,
+    Omission,
+    Transposition,
+}
+
+// Number of target characters affected by each kind of error, plus the extra characters typed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub substitutions: usize,
+    pub omissions: usize,
+    pub insertions: usize,
+    pub transpositions: usize,
+}
+
+// Optimal string alignment of a typed word with its target. Returns one mark per target
+// character and the number of inserted characters, which have no target character to mark.
+pub fn align(target: &[char], typed: &[char]) -> (Vec<Mark>, usize) {
+    let (m, n) = (target.len(), typed.len());
+
+    let mut d = vec![vec![0; n + 1]; m + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    let transposed = |i: usize, j: usize| {
+        i > 1 && j > 1 && target[i - 1] == typed[j - 2] && target[i - 2] == typed[j - 1]
+    };
+
+    for i in 1..=m {
+        for j in 1..=n {
+            let cost = usize::from(target[i - 1] != typed[j - 1]);
+            let mut best = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if transposed(i, j) {
+                best = best.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = best;
+        }
+    }
+
+    let mut marks = vec![Mark::Correct; m];
+    let mut insertions = 0;
+    let (mut i, mut j) = (m, n);
+
+    while i > 0 || j > 0 {
+        if i > 0 && j > 0 && target[i - 1] == typed[j - 1] && d[i][j] == d[i - 1][j - 1] {
+            marks[i - 1] = Mark::Correct;
+            i -= 1;
+            j -= 1;
+        } else if transposed(i, j) && d[i][j] == d[i - 2][j - 2] + 1 {
+            marks[i - 1] = Mark::Transposition;
+            marks[i - 2] = Mark::Transposition;
+            i -= 2;
+            j -= 2;
+        } else if i > 0 && j > 0 && d[i][j] == d[i - 1][j - 1] + 1 {
+            marks[i - 1] = Mark::Substitution;
+            i -= 1;
+            j -= 1;
+        } else if i > 0 && d[i][j] == d[i - 1][j] + 1 {
+            marks[i - 1] = Mark::Omission;
+            i -= 1;
+        } else {
+            insertions += 1;
+            j -= 1;
+        }
+    }
+
+    (marks, insertions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn align_str(target: &str, typed: &str) -> (Vec<Mark>, usize) {
+        let target: Vec<char> = target.chars().collect();
+        let typed: Vec<char> = typed.chars().collect();
+        align(&target, &typed)
+    }
+
+    #[test]
+    fn classifies_single_errors() {
+        use Mark::*;
+
+        assert_eq!(align_str("hello", "hello"), (vec![Correct; 5], 0));
+        assert_eq!(
+            align_str("hello", "hallo"),
+            (vec![Correct, Substitution, Correct, Correct, Correct], 0)
+        );
+        assert_eq!(
+            align_str("hello", "hllo"),
+            (vec![Correct, Omission, Correct, Correct, Correct], 0)
+        );
+        assert_eq!(align_str("hello", "hellxo"), (vec![Correct; 5], 1));
+        assert_eq!(
+            align_str("hello", "hlelo"),
+            (
+                vec![Correct, Transposition, Transposition, Correct, Correct],
+                0
+            )
+        );
+    }
+
+    #[test]
+    fn aligns_empty_words() {
+        assert_eq!(align_str("ab", ""), (vec![Mark::Omission; 2], 0));
+        assert_eq!(align_str("", "ab"), (Vec::new(), 2));
+    }
+}
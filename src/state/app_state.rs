@@ -1,51 +1,498 @@
 use super::actions::{Action, TimedAction};
-use super::home::HomeState;
+use super::home::{HomeState, MenuItem};
+use super::picker::PickerState;
 use super::type_test::TypeTestState;
-use crate::keyboard::Keyboard;
+use crate::bindings::Bindings;
+use crate::clock::Clock;
+use crate::heatmap::{history_samples, merge, Heatmap, HeatmapScope};
+use crate::history::{History, SessionRecord};
+use crate::keyboard::{Emulation, Keyboard};
+use crate::playlist::Segment;
+use crate::source::TextSource;
+use crate::utils::canonical_source;
+use crossterm::event::{KeyEvent, KeyEventKind};
+use std::{collections::HashSet, rc::Rc};
 
 pub enum StateError {
     NoActionYet,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum View {
     Home,
+    // Browses the directories for a file
+    Picker,
+    // Asks for the path of a file to practice
+    PathInput,
     TypeTest,
+    Results,
+    History,
 }
 
 pub struct State {
-    pub current_view: View,
+    // The view shown is the last one, the home menu is always at the bottom
+    pub views: Vec<View>,
     pub home: HomeState,
+    pub picker: PickerState,
     pub type_test: TypeTestState,
+    pub should_quit: bool,
+    // Waiting for the quit to be confirmed
+    pub confirm_quit: bool,
+    pub source: Option<TextSource>,
+    pub history: History,
+    pub history_error: Option<String>,
+    pub clock: Rc<dyn Clock>,
+    // Remaps what is typed during a test
+    pub emulation: Option<Emulation>,
+    // Tints the keys of the keyboard
+    pub heatmap: Option<Heatmap>,
+    // Letter unlocked by the last curriculum session
+    pub unlocked_key: Option<char>,
 }
 
 impl State {
-    pub fn new(keyboard: Keyboard) -> State {
+    pub fn new(keyboard: Keyboard, history: History, clock: Rc<dyn Clock>) -> State {
         State {
-            current_view: View::TypeTest,
-            home: HomeState {},
-            type_test: TypeTestState {
-                cursor: (0, 0),
-                last_modified: None,
-                lines: Vec::new(),
-                actions: Vec::new(),
-                keyboard,
-            },
+            views: vec![View::Home],
+            home: HomeState::new(),
+            picker: PickerState::new(),
+            type_test: TypeTestState::new(keyboard, clock.clone()),
+            should_quit: false,
+            confirm_quit: false,
+            source: None,
+            history,
+            history_error: None,
+            clock,
+            emulation: None,
+            heatmap: None,
+            unlocked_key: None,
+        }
+    }
+
+    fn save_session(&mut self) {
+        let source = self.source.as_ref().map_or(String::new(), |s| s.describe());
+        let stats = self.type_test.stats();
+        let record = SessionRecord::new(&source, &self.type_test, &stats, self.clock.as_ref());
+        self.history_error = self.history.append(&record).err().map(|e| e.to_string());
+        if let Some(heatmap) = self
+            .heatmap
+            .as_mut()
+            .filter(|h| h.scope == HeatmapScope::History)
+        {
+            merge(&mut heatmap.past, &history_samples(&[record]));
+        }
+
+        if let Some(TextSource::Playlist(playlist)) = &mut self.source {
+            playlist.record(Segment::new(&source, &stats));
+        }
+
+        self.unlocked_key = None;
+        if let Some(TextSource::Curriculum(options)) = &self.source {
+            match options.record(&self.type_test.keystrokes) {
+                Ok(unlocked) => self.unlocked_key = unlocked,
+                Err(e) => self.history_error = Some(e.to_string()),
+            }
+        }
+    }
+
+    pub fn current_view(&self) -> View {
+        *self.views.last().unwrap()
+    }
+
+    pub fn push_view(&mut self, view: View) {
+        self.views.push(view);
+    }
+
+    pub fn pop_view(&mut self) {
+        if self.views.len() > 1 {
+            self.views.pop();
+        }
+    }
+
+    // Back to the menu, with the files of the sessions saved since it was last shown
+    pub fn go_home(&mut self) {
+        self.views.truncate(1);
+        match self.history.load_with_skipped() {
+            Ok((sessions, skipped)) => {
+                if skipped > 0 {
+                    self.home.error = Some(format!(
+                        "Skipped {} unreadable lines of the history",
+                        skipped
+                    ));
+                }
+                self.home.refresh(sessions);
+            }
+            Err(e) => self.home.error = Some(format!("Could not read history: {}", e)),
+        }
+    }
+
+    fn finish_if_done(&mut self) {
+        if self.type_test.is_finished() {
+            self.save_session();
+            self.push_view(View::Results);
+        }
+    }
+
+    // Back from the results to the same text
+    fn retry(&mut self) {
+        self.type_test.reset();
+        if self.current_view() == View::Results {
+            self.pop_view();
+        }
+    }
+
+    // Opens the picker where it was left, in the working directory the first time
+    fn browse(&mut self) {
+        let dir = self.picker.dir.clone();
+        self.picker.open(&dir);
+        self.picker.set_history(&self.home.sessions);
+        self.push_view(View::Picker);
+    }
+
+    // A new text from the same source, or a file from the picker when there is none
+    fn next_text(&mut self) {
+        let Some(mut source) = self.source.as_ref().and_then(|s| s.next()) else {
+            self.go_home();
+            self.home.mode = self.type_test.mode;
+            self.browse();
+            return;
+        };
+
+        loop {
+            match source.load() {
+                Ok(target) => return self.start(source, target),
+                // Playlists go on with the following file
+                Err(e) => match source {
+                    TextSource::Playlist(mut playlist) => {
+                        if !playlist.skip(e) {
+                            self.source = Some(TextSource::Playlist(playlist));
+                            return;
+                        }
+                        source = TextSource::Playlist(playlist);
+                    }
+                    _ => {
+                        self.go_home();
+                        self.home.error = Some(e);
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    fn select(&mut self, item: MenuItem, time: u128) {
+        let seed = self.clock.to_unix_millis(time);
+        match item {
+            MenuItem::File => self.browse(),
+            MenuItem::History => self.push_view(View::History),
+            item => {
+                let Some((source, mode)) =
+                    self.home
+                        .source(&item, seed, &self.type_test.keyboard, &self.history)
+                else {
+                    return;
+                };
+                if let Some((source, target)) = self.home.load(source) {
+                    self.type_test.mode = mode;
+                    self.start(source, target);
+                }
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let time = self.clock.now();
+        self.type_test.keyboard.tick(time);
+
+        if self.current_view() == View::TypeTest {
+            self.type_test.tick(time);
+            self.finish_if_done();
         }
     }
 
-    pub fn dispatch(&mut self, action: Action) {
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
+    pub fn start(&mut self, source: TextSource, target: String) {
+        self.type_test.keyboard.locked_keys = match &source {
+            TextSource::Curriculum(options) => options.locked_keys(&self.type_test.keyboard),
+            _ => HashSet::new(),
+        };
+        self.source = Some(source);
+        self.type_test.dispatch(TimedAction {
+            action: Action::SetTarget(target),
+            time: self.clock.now(),
+            released: None,
+        });
+        self.views.truncate(1);
+        self.push_view(View::TypeTest);
+    }
+
+    // Releases are only reported by the terminals supporting the kitty keyboard protocol
+    pub fn handle_key(&mut self, key: KeyEvent, bindings: &Bindings, time: u128) {
+        match (key.kind, bindings.resolve(key)) {
+            (KeyEventKind::Release, Some(Action::Char(c))) => self.release(c, time),
+            (KeyEventKind::Release, _) => {}
+            (_, Some(action)) => self.dispatch(action, time),
+            (_, None) => {}
+        }
+    }
 
+    // Key releases only time the keystrokes of the test
+    pub fn release(&mut self, c: char, time: u128) {
+        if let View::TypeTest | View::Results = self.current_view() {
+            let c = self.emulation.as_ref().map_or(c, |e| e.translate(c));
+            self.type_test.release(c, time);
+        }
+    }
+
+    // `time` is read from the clock when the key event is read, not when it is handled
+    pub fn dispatch(&mut self, action: Action, time: u128) {
         // self.actions.push(TimedAction { action, time });
 
-        match self.current_view {
-            View::Home => {}
+        if self.confirm_quit {
+            self.confirm_quit = false;
+            self.should_quit = matches!(action, Action::Quit | Action::Char('y' | 'Y' | '\n'));
+            return;
+        }
+
+        match (self.current_view(), &action) {
+            (_, Action::Quit) => {
+                self.confirm_quit = true;
+                return;
+            }
+            (View::TypeTest | View::Results, Action::Restart) => {
+                self.retry();
+                return;
+            }
+            (View::Picker | View::PathInput | View::History, Action::Restart) => {
+                self.pop_view();
+                return;
+            }
+            _ => {}
+        }
+
+        match self.current_view() {
+            View::Home => match action {
+                Action::Up => self.home.select_previous(),
+                Action::Down => self.home.select_next(),
+                Action::Left | Action::Right if self.home.selected_item() == MenuItem::Timed => {
+                    self.home.change_duration(matches!(action, Action::Right))
+                }
+                Action::Char('\n') => self.select(self.home.selected_item(), time),
+                Action::Char('\t') => self.home.mode = self.home.mode.next(),
+                Action::Char('q') => self.should_quit = true,
+                _ => {}
+            },
+            View::Picker => match action {
+                Action::Up => self.picker.select_previous(),
+                Action::Down => self.picker.select_next(),
+                Action::Left => self.picker.change_filter(false),
+                Action::Right => self.picker.change_filter(true),
+                Action::Backspace => self.picker.open_parent(),
+                Action::Char('\n') => {
+                    if let Some(path) = self.picker.enter() {
+                        let source =
+                            TextSource::File(canonical_source(&path.display().to_string()));
+                        match source.load() {
+                            Ok(target) => {
+                                self.type_test.mode = self.home.mode;
+                                self.start(source, target);
+                            }
+                            Err(e) => self.picker.error = Some(e),
+                        }
+                    }
+                }
+                Action::Char('\t') => self.home.mode = self.home.mode.next(),
+                Action::Char('p') => {
+                    self.home.clear();
+                    self.push_view(View::PathInput);
+                }
+                Action::Char('q') => self.pop_view(),
+                _ => {}
+            },
+            View::PathInput => match action {
+                Action::Char('\n') => {
+                    if let Some((source, target)) = self.home.load_target() {
+                        self.type_test.mode = self.home.mode;
+                        self.start(source, target);
+                    }
+                }
+                Action::Char('\t') => self.home.mode = self.home.mode.next(),
+                Action::Char(c) => self.home.path.push(c),
+                Action::Backspace => {
+                    self.home.path.pop();
+                }
+                Action::DeleteWord => {
+                    let path = self.home.path.trim_end_matches('/');
+                    let len = path.rfind('/').map_or(0, |i| i + 1);
+                    self.home.path.truncate(len);
+                }
+                Action::ClearLine => self.home.path.clear(),
+                _ => {}
+            },
             View::TypeTest => {
-                self.type_test.dispatch(TimedAction { action, time });
+                let action = match (action, &self.emulation) {
+                    (Action::Char(c), Some(emulation)) => Action::Char(emulation.translate(c)),
+                    (action, _) => action,
+                };
+                self.type_test.dispatch(TimedAction {
+                    action,
+                    time,
+                    released: None,
+                });
+                self.finish_if_done();
+            }
+            View::Results => match action {
+                Action::Char('r') => self.retry(),
+                Action::Char('n') => self.next_text(),
+                Action::Char('h') => self.go_home(),
+                Action::Char('q') => self.should_quit = true,
+                _ => {}
+            },
+            View::History => {
+                if let Action::Char('\n' | 'q') = action {
+                    self.pop_view();
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::keyboard::LayoutSource;
+    use crate::playlist::Playlist;
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    fn new_state() -> State {
+        let keyboard = LayoutSource::Builtin(String::from("qwerty"))
+            .load()
+            .unwrap();
+        // Nowhere to save the sessions
+        State::new(keyboard, History::new(None), Rc::new(ManualClock::new()))
+    }
+
+    #[test]
+    fn moves_through_the_views() {
+        let mut state = new_state();
+        assert_eq!(state.current_view(), View::Home);
+
+        state.dispatch(Action::Up, 0);
+        assert_eq!(state.home.selected_item(), MenuItem::History);
+        state.dispatch(Action::Char('\n'), 0);
+        assert_eq!(state.views, vec![View::Home, View::History]);
+        state.dispatch(Action::Char('q'), 0);
+        assert_eq!(state.current_view(), View::Home);
+
+        state.dispatch(Action::Down, 0);
+        state.dispatch(Action::Down, 0);
+        assert_eq!(state.home.selected_item(), MenuItem::Words);
+        state.dispatch(Action::Char('\n'), 0);
+        assert_eq!(state.views, vec![View::Home, View::TypeTest]);
+
+        state.start(TextSource::File(String::from("test")), String::from("ab"));
+        state.dispatch(Action::Char('a'), 0);
+        state.dispatch(Action::Char('b'), 0);
+        assert_eq!(state.views, vec![View::Home, View::TypeTest, View::Results]);
+        state.dispatch(Action::Char('r'), 0);
+        assert_eq!(state.current_view(), View::TypeTest);
+        state.dispatch(Action::Char('a'), 0);
+        state.dispatch(Action::Char('b'), 0);
+        state.dispatch(Action::Char('h'), 0);
+        assert_eq!(state.views, vec![View::Home]);
+    }
+
+    #[test]
+    fn skips_unreadable_playlist_files() {
+        let dir = std::env::temp_dir().join(format!("blitzkey-skip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| dir.join(file).display().to_string();
+        let files = ["a", "b", "c", "d"];
+        for file in files {
+            std::fs::write(path(file), file).unwrap();
+        }
+        let mut playlist = Playlist::new(files.map(path).to_vec(), Vec::new());
+        playlist.collect().unwrap();
+        // Gone once the session started
+        std::fs::remove_file(path("b")).unwrap();
+        std::fs::remove_file(path("d")).unwrap();
+
+        let mut state = new_state();
+        let source = TextSource::Playlist(playlist);
+        let target = source.load().unwrap();
+        state.start(source, target);
+        state.dispatch(Action::Char('a'), 0);
+        state.dispatch(Action::Char('n'), 0);
+        assert_eq!(state.current_view(), View::TypeTest);
+        assert_eq!(state.source.as_ref().unwrap().describe(), path("c"));
+
+        // The last file is gone too, the playlist ends on the results of "c"
+        state.dispatch(Action::Char('c'), 0);
+        state.dispatch(Action::Char('n'), 0);
+        assert_eq!(state.current_view(), View::Results);
+        let Some(TextSource::Playlist(playlist)) = &state.source else {
+            unreachable!()
+        };
+        assert_eq!(playlist.files, vec![path("a"), path("c")]);
+        assert_eq!(playlist.skipped.len(), 2);
+        assert!(playlist.summary().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finished_sessions_join_the_history_heatmap() {
+        let mut state = new_state();
+        state.heatmap = Some(Heatmap::new(
+            crate::heatmap::HeatmapMetric::Accuracy,
+            HeatmapScope::History,
+        ));
+        state.start(TextSource::File(String::from("test")), String::from("ab"));
+        state.dispatch(Action::Char('a'), 0);
+        state.dispatch(Action::Char('x'), 10);
+        assert_eq!(state.current_view(), View::Results);
+
+        let past = &state.heatmap.as_ref().unwrap().past;
+        assert_eq!((past[&'a'].total, past[&'a'].valid), (1, 1));
+        assert_eq!((past[&'b'].total, past[&'b'].valid), (1, 0));
+    }
+
+    #[test]
+    fn releases_reach_the_test() {
+        let mut state = new_state();
+        let bindings = Bindings::defaults();
+        let key = |c: char, modifiers: KeyModifiers, kind: KeyEventKind| {
+            KeyEvent::new_with_kind(KeyCode::Char(c), modifiers, kind)
+        };
+        state.start(TextSource::File(String::from("test")), String::from("Ab"));
+
+        // Unshifted character and Shift, as sent when every key is an escape code
+        state.handle_key(
+            key('a', KeyModifiers::SHIFT, KeyEventKind::Press),
+            &bindings,
+            10,
+        );
+        state.handle_key(
+            key('b', KeyModifiers::NONE, KeyEventKind::Press),
+            &bindings,
+            20,
+        );
+        // Shift let go first
+        state.handle_key(
+            key('a', KeyModifiers::NONE, KeyEventKind::Release),
+            &bindings,
+            30,
+        );
+        state.handle_key(
+            key('b', KeyModifiers::NONE, KeyEventKind::Release),
+            &bindings,
+            50,
+        );
+
+        let actions = &state.type_test.actions;
+        assert!(matches!(actions[0].action, Action::Char('A')));
+        assert_eq!(actions[0].released, Some(30));
+        assert_eq!(actions[1].released, Some(50));
+        assert_eq!(state.current_view(), View::Results);
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// What the typist is allowed to do about their mistakes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrectionPolicy {
+    #[default]
+    Free,
+    // Backspace is ignored
+    Disabled,
+    // Backspace can't go back past the start of the current word
+    WithinWord,
+    // Backspace can't go back into a word that was typed correctly
+    NoCorrectWord,
+    // The cursor only advances on the expected key
+    StopOnError,
+    // A word can only be left once it is typed correctly
+    StopOnWordError,
+}
+
+pub const CORRECTION_POLICIES: [CorrectionPolicy; 6] = [
+    CorrectionPolicy::Free,
+    CorrectionPolicy::Disabled,
+    CorrectionPolicy::WithinWord,
+    CorrectionPolicy::NoCorrectWord,
+    CorrectionPolicy::StopOnError,
+    CorrectionPolicy::StopOnWordError,
+];
+
+impl CorrectionPolicy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CorrectionPolicy::Free => "free",
+            CorrectionPolicy::Disabled => "no-backspace",
+            CorrectionPolicy::WithinWord => "within-word",
+            CorrectionPolicy::NoCorrectWord => "no-correct-word",
+            CorrectionPolicy::StopOnError => "stop-on-error",
+            CorrectionPolicy::StopOnWordError => "stop-on-word-error",
+        }
+    }
+}
+
+impl fmt::Display for CorrectionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
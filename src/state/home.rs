@@ -1 +1,191 @@
-pub struct HomeState {}
+use std::path::Path;
+
+use super::type_test::{TestMode, TIMED_DURATIONS};
+use crate::adaptive::AdaptiveOptions;
+use crate::curriculum::{self, CurriculumOptions, ProgressStore, DEFAULT_GOALS, LESSON_WORDS};
+use crate::history::{History, SessionRecord};
+use crate::keyboard::Keyboard;
+use crate::source::TextSource;
+use crate::utils::canonical_source;
+use crate::words::{words_for_duration, WordsOptions, DEFAULT_WORD_LIST};
+
+// Words of a generated text outside of timed tests
+const PRACTICE_WORDS: usize = 50;
+
+// Files of the history listed on the menu
+const RECENT_FILES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuItem {
+    File,
+    Words,
+    Timed,
+    Lessons,
+    Adaptive,
+    History,
+    Recent(String),
+}
+
+pub struct HomeState {
+    pub selected: usize,
+    // Of the timed words
+    pub duration: u64,
+    // Shown on the menu, e.g. "qwerty"
+    pub layout: String,
+    // Latest first
+    pub recent: Vec<String>,
+    pub sessions: Vec<SessionRecord>,
+    // Typed in the file prompt
+    pub path: String,
+    // Of the files, recent ones included
+    pub mode: TestMode,
+    pub error: Option<String>,
+}
+
+impl HomeState {
+    pub fn new() -> HomeState {
+        HomeState {
+            selected: 0,
+            duration: TIMED_DURATIONS[1],
+            layout: String::new(),
+            recent: Vec::new(),
+            sessions: Vec::new(),
+            path: String::new(),
+            mode: TestMode::Full,
+            error: None,
+        }
+    }
+
+    pub fn items(&self) -> Vec<MenuItem> {
+        let mut items = vec![
+            MenuItem::File,
+            MenuItem::Words,
+            MenuItem::Timed,
+            MenuItem::Lessons,
+            MenuItem::Adaptive,
+            MenuItem::History,
+        ];
+        items.extend(self.recent.iter().cloned().map(MenuItem::Recent));
+        items
+    }
+
+    pub fn selected_item(&self) -> MenuItem {
+        let items = self.items();
+        items[self.selected.min(items.len() - 1)].clone()
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % self.items().len();
+    }
+
+    pub fn select_previous(&mut self) {
+        let len = self.items().len();
+        self.selected = (self.selected + len - 1) % len;
+    }
+
+    // Steps through the durations of the timed words
+    pub fn change_duration(&mut self, forward: bool) {
+        let i = TIMED_DURATIONS
+            .iter()
+            .position(|d| *d == self.duration)
+            .unwrap_or(0);
+        let len = TIMED_DURATIONS.len();
+        self.duration = TIMED_DURATIONS[if forward {
+            (i + 1) % len
+        } else {
+            (i + len - 1) % len
+        }];
+    }
+
+    // The files practiced lately that still exist
+    pub fn refresh(&mut self, sessions: Vec<SessionRecord>) {
+        self.recent.clear();
+        for session in sessions.iter().rev() {
+            if self.recent.len() == RECENT_FILES {
+                break;
+            }
+            if !self.recent.contains(&session.source) && Path::new(&session.source).is_file() {
+                self.recent.push(session.source.clone());
+            }
+        }
+        self.sessions = sessions;
+        self.selected = self.selected.min(self.items().len() - 1);
+    }
+
+    pub fn clear(&mut self) {
+        self.path.clear();
+        self.error = None;
+    }
+
+    // The text of a menu item and the mode of its test. `None` for the items that don't
+    // start a test.
+    pub fn source(
+        &self,
+        item: &MenuItem,
+        seed: u64,
+        keyboard: &Keyboard,
+        history: &History,
+    ) -> Option<(TextSource, TestMode)> {
+        let words = |count| WordsOptions {
+            list: String::from(DEFAULT_WORD_LIST),
+            count,
+            punctuation: false,
+            numbers: false,
+            capitalize: false,
+            seed,
+        };
+        let count = |mode| match mode {
+            TestMode::Timed(seconds) => words_for_duration(seconds),
+            TestMode::Full => PRACTICE_WORDS,
+        };
+
+        match item {
+            MenuItem::File | MenuItem::History => None,
+            MenuItem::Words => Some((TextSource::Words(words(PRACTICE_WORDS)), TestMode::Full)),
+            MenuItem::Timed => {
+                let mode = TestMode::Timed(self.duration);
+                Some((TextSource::Words(words(count(mode))), mode))
+            }
+            MenuItem::Lessons => Some((
+                TextSource::Curriculum(CurriculumOptions {
+                    count: LESSON_WORDS,
+                    seed,
+                    layout: keyboard.name.clone(),
+                    order: curriculum::letter_order(keyboard),
+                    goals: DEFAULT_GOALS,
+                    store: ProgressStore::open_default(),
+                }),
+                TestMode::Full,
+            )),
+            MenuItem::Adaptive => Some((
+                TextSource::Adaptive(AdaptiveOptions {
+                    list: String::from(DEFAULT_WORD_LIST),
+                    count: count(self.mode),
+                    seed,
+                    history: history.clone(),
+                }),
+                self.mode,
+            )),
+            MenuItem::Recent(path) => Some((TextSource::File(path.clone()), self.mode)),
+        }
+    }
+
+    // Reads the file at the typed path, keeping the error around so the view can show it.
+    pub fn load_target(&mut self) -> Option<(TextSource, String)> {
+        let source = TextSource::File(canonical_source(self.path.trim()));
+        self.load(source)
+    }
+
+    pub fn load(&mut self, source: TextSource) -> Option<(TextSource, String)> {
+        match source.load() {
+            Ok(target) => {
+                self.error = None;
+                Some((source, target))
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+}
@@ -1,9 +1,19 @@
 mod actions;
+mod alignment;
 mod app_state;
+mod correction;
 mod home;
+mod ngrams;
+mod picker;
 // mod type_test;
 mod type_test;
 
-pub use actions::{Action, TimedAction};
+pub use actions::{Action, TimedAction, IDLE_LATENCY};
+pub use alignment::{AlignmentMode, Mark};
 pub use app_state::*;
-pub use type_test::ElementValue;
+pub use correction::{CorrectionPolicy, CORRECTION_POLICIES};
+pub use home::{HomeState, MenuItem};
+pub use ngrams::{merge_ngrams, most_error_prone, slowest, Keystroke, NgramStats, MIN_OCCURRENCES};
+pub use type_test::{
+    keys_precision, ElementValue, KeysStats, StateStats, TestMode, TypeTestState, TIMED_DURATIONS,
+};
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::actions::IDLE_LATENCY;
+
+// Occurrences needed before an n-gram is ranked
+pub const MIN_OCCURRENCES: u32 = 2;
+
+// A character typed on the target text. `run` changes with every correction or pause, so
+// n-grams only span keystrokes typed in one go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keystroke {
+    pub expected: char,
+    pub typed: char,
+    pub time: u128,
+    pub run: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NgramStats {
+    pub ngram: String,
+    // Times the n-gram was attempted, its first characters typed correctly
+    pub count: u32,
+    // Attempts whose last character was wrong
+    pub errors: u32,
+    // Time from the first to the last keystroke of the correct attempts, summed
+    pub latency: u128,
+    pub timed: u32,
+}
+
+impl NgramStats {
+    pub fn mean_latency(&self) -> Option<f32> {
+        (self.timed > 0).then(|| self.latency as f32 / self.timed as f32)
+    }
+
+    pub fn error_rate(&self) -> f32 {
+        self.errors as f32 / self.count.max(1) as f32
+    }
+
+    fn add(&mut self, other: &NgramStats) {
+        self.count += other.count;
+        self.errors += other.errors;
+        self.latency += other.latency;
+        self.timed += other.timed;
+    }
+}
+
+// Bigrams and trigrams of the text, sorted by n-gram. Those spanning whitespace are left out.
+pub fn ngram_stats(keystrokes: &[Keystroke]) -> Vec<NgramStats> {
+    let mut ngrams: HashMap<String, NgramStats> = HashMap::new();
+
+    for n in [2, 3] {
+        for window in keystrokes.windows(n) {
+            let (first, last) = (&window[0], &window[n - 1]);
+            if window
+                .iter()
+                .any(|k| k.run != first.run || k.expected.is_whitespace())
+                || window[..n - 1].iter().any(|k| k.typed != k.expected)
+            {
+                continue;
+            }
+
+            let ngram: String = window.iter().map(|k| k.expected).collect();
+            let stats = ngrams.entry(ngram.clone()).or_insert(NgramStats {
+                ngram,
+                count: 0,
+                errors: 0,
+                latency: 0,
+                timed: 0,
+            });
+            stats.count += 1;
+
+            let latency = last.time.saturating_sub(first.time);
+            if last.typed != last.expected {
+                stats.errors += 1;
+            } else if latency <= IDLE_LATENCY * (n as u128 - 1) {
+                stats.latency += latency;
+                stats.timed += 1;
+            }
+        }
+    }
+
+    let mut ngrams: Vec<NgramStats> = ngrams.into_values().collect();
+    ngrams.sort_by(|a, b| a.ngram.cmp(&b.ngram));
+    ngrams
+}
+
+pub fn merge_ngrams(into: &mut Vec<NgramStats>, from: &[NgramStats]) {
+    for stats in from {
+        match into.iter_mut().find(|s| s.ngram == stats.ngram) {
+            Some(existing) => existing.add(stats),
+            None => into.push(stats.clone()),
+        }
+    }
+}
+
+pub fn slowest(ngrams: &[NgramStats], limit: usize) -> Vec<NgramStats> {
+    let mut ranked: Vec<&NgramStats> = ngrams
+        .iter()
+        .filter(|s| s.timed >= MIN_OCCURRENCES)
+        .collect();
+    // Only the timed ones are ranked, they all have a mean latency
+    ranked.sort_by(|a, b| {
+        let latency = |s: &NgramStats| s.mean_latency().unwrap_or(0.0);
+        latency(b).total_cmp(&latency(a))
+    });
+    ranked.into_iter().take(limit).cloned().collect()
+}
+
+pub fn most_error_prone(ngrams: &[NgramStats], limit: usize) -> Vec<NgramStats> {
+    let mut ranked: Vec<&NgramStats> = ngrams
+        .iter()
+        .filter(|s| s.count >= MIN_OCCURRENCES && s.errors > 0)
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.error_rate()
+            .total_cmp(&a.error_rate())
+            .then(b.errors.cmp(&a.errors))
+    });
+    ranked.into_iter().take(limit).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keystrokes(typed: &[(char, char, u128, u32)]) -> Vec<Keystroke> {
+        typed
+            .iter()
+            .map(|(expected, typed, time, run)| Keystroke {
+                expected: *expected,
+                typed: *typed,
+                time: *time,
+                run: *run,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn times_correct_ngrams() {
+        // "the the" with a miss on the second 'e', then "th" again after a correction
+        let ngrams = ngram_stats(&keystrokes(&[
+            ('t', 't', 0, 0),
+            ('h', 'h', 100, 0),
+            ('e', 'e', 250, 0),
+            (' ', ' ', 300, 0),
+            ('t', 't', 400, 0),
+            ('h', 'h', 500, 0),
+            ('e', 'r', 600, 0),
+            ('t', 't', 1000, 1),
+            ('h', 'h', 1300, 1),
+        ]));
+        let get = |ngram: &str| ngrams.iter().find(|s| s.ngram == ngram).unwrap();
+
+        assert_eq!(get("th").count, 3);
+        assert_eq!(get("th").mean_latency(), Some(500.0 / 3.0));
+        assert_eq!(get("he").count, 2);
+        assert_eq!(get("he").errors, 1);
+        assert_eq!(get("the").latency, 250);
+        assert!(ngrams.iter().all(|s| !s.ngram.contains(' ')));
+
+        assert_eq!(slowest(&ngrams, 1)[0].ngram, "th");
+        assert_eq!(most_error_prone(&ngrams, 5)[0].ngram, "he");
+    }
+
+    #[test]
+    fn merges_sessions() {
+        let session = ngram_stats(&keystrokes(&[('a', 'a', 0, 0), ('b', 'b', 100, 0)]));
+        let mut all = Vec::new();
+        merge_ngrams(&mut all, &session);
+        merge_ngrams(&mut all, &session);
+
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].count, 2);
+        assert_eq!(all[0].latency, 200);
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::history::SessionRecord;
+use crate::utils::canonical_source;
+
+// Lines of the selected file shown next to the list
+pub const PREVIEW_LINES: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PickerEntry {
+    // ".." for the parent directory
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+}
+
+// Browses the directories for a file to practice
+pub struct PickerState {
+    pub dir: PathBuf,
+    // Directories first, then files, by name. Hidden ones are left out.
+    pub entries: Vec<PickerEntry>,
+    // Index in the visible entries
+    pub selected: usize,
+    // Extensions of the files of the directory, sorted
+    pub extensions: Vec<String>,
+    // Only the files with this extension are listed, directories always are
+    pub filter: Option<String>,
+    pub preview: Vec<String>,
+    // Best words per minute of each file practiced, by canonical path
+    pub best: HashMap<String, f32>,
+    pub error: Option<String>,
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_string())
+}
+
+impl PickerState {
+    pub fn new() -> PickerState {
+        PickerState {
+            dir: PathBuf::from("."),
+            entries: Vec::new(),
+            selected: 0,
+            extensions: Vec::new(),
+            filter: None,
+            preview: Vec::new(),
+            best: HashMap::new(),
+            error: None,
+        }
+    }
+
+    pub fn open(&mut self, dir: &Path) {
+        let dir = std::fs::canonicalize(dir).unwrap_or(dir.to_path_buf());
+        let read = std::fs::read_dir(&dir).and_then(|entries| {
+            entries
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok(PickerEntry {
+                        name: entry.file_name().to_string_lossy().to_string(),
+                        path: entry.path(),
+                        // Follows links, unlike the file type of the entry
+                        is_dir: entry.path().is_dir(),
+                    })
+                })
+                .collect::<std::io::Result<Vec<_>>>()
+        });
+        let mut entries = match read {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.error = Some(format!("{}: {}", dir.display(), e));
+                return;
+            }
+        };
+
+        entries.retain(|e| !e.name.starts_with('.'));
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then(a.name.cmp(&b.name)));
+        self.dir = dir;
+        if let Some(parent) = self.dir.parent() {
+            entries.insert(
+                0,
+                PickerEntry {
+                    name: String::from(".."),
+                    path: parent.to_path_buf(),
+                    is_dir: true,
+                },
+            );
+        }
+
+        self.extensions = entries
+            .iter()
+            .filter(|e| !e.is_dir)
+            .filter_map(|e| extension(&e.path))
+            .collect();
+        self.extensions.sort();
+        self.extensions.dedup();
+        self.entries = entries;
+        self.selected = 0;
+        self.error = None;
+        self.update_preview();
+    }
+
+    pub fn set_history(&mut self, sessions: &[SessionRecord]) {
+        self.best.clear();
+        for session in sessions {
+            if let Some(wpm) = session.words_per_minute {
+                let best = self.best.entry(session.source.clone()).or_insert(wpm);
+                *best = best.max(wpm);
+            }
+        }
+    }
+
+    pub fn visible(&self) -> Vec<&PickerEntry> {
+        self.entries
+            .iter()
+            .filter(|e| e.is_dir || self.filter.is_none() || extension(&e.path) == self.filter)
+            .collect()
+    }
+
+    pub fn selected_entry(&self) -> Option<&PickerEntry> {
+        self.visible().get(self.selected).copied()
+    }
+
+    pub fn best_wpm(&self, entry: &PickerEntry) -> Option<f32> {
+        if entry.is_dir {
+            return None;
+        }
+        self.best
+            .get(&canonical_source(&entry.path.display().to_string()))
+            .copied()
+    }
+
+    pub fn select_next(&mut self) {
+        let len = self.visible().len().max(1);
+        self.selected = (self.selected + 1) % len;
+        self.update_preview();
+    }
+
+    pub fn select_previous(&mut self) {
+        let len = self.visible().len().max(1);
+        self.selected = (self.selected + len - 1) % len;
+        self.update_preview();
+    }
+
+    // Steps through no filter and the extensions of the directory
+    pub fn change_filter(&mut self, forward: bool) {
+        let mut filters: Vec<Option<String>> = vec![None];
+        filters.extend(self.extensions.iter().cloned().map(Some));
+        if !filters.contains(&self.filter) {
+            filters.push(self.filter.clone());
+        }
+        let i = filters.iter().position(|f| *f == self.filter).unwrap();
+        let len = filters.len();
+        self.filter = filters[if forward {
+            (i + 1) % len
+        } else {
+            (i + len - 1) % len
+        }]
+        .clone();
+        self.selected = 0;
+        self.update_preview();
+    }
+
+    pub fn open_parent(&mut self) {
+        if let Some(parent) = self.dir.parent().map(Path::to_path_buf) {
+            self.open(&parent);
+        }
+    }
+
+    // Opens the selected directory, or returns the selected file
+    pub fn enter(&mut self) -> Option<PathBuf> {
+        let entry = self.selected_entry()?.clone();
+        if entry.is_dir {
+            self.open(&entry.path);
+            None
+        } else {
+            Some(entry.path)
+        }
+    }
+
+    fn update_preview(&mut self) {
+        self.preview = match self.selected_entry() {
+            Some(entry) if !entry.is_dir => match File::open(&entry.path) {
+                Ok(file) => BufReader::new(file)
+                    .lines()
+                    .take(PREVIEW_LINES)
+                    .map_while(Result::ok)
+                    .collect(),
+                Err(e) => vec![e.to_string()],
+            },
+            _ => Vec::new(),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browses_and_filters() {
+        let dir = std::env::temp_dir().join(format!("blitzkey-picker-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("a.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+
+        let mut picker = PickerState::new();
+        picker.open(&dir);
+        let names = |picker: &PickerState| -> Vec<String> {
+            picker.visible().iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(&picker), vec!["..", "sub", "a.rs", "b.txt"]);
+        assert_eq!(picker.extensions, vec!["rs", "txt"]);
+
+        picker.change_filter(false);
+        assert_eq!(names(&picker), vec!["..", "sub", "b.txt"]);
+        picker.select_previous();
+        assert_eq!(picker.preview, vec!["one", "two"]);
+        assert_eq!(
+            picker.enter(),
+            Some(dir.canonicalize().unwrap().join("b.txt"))
+        );
+
+        picker.select_next();
+        picker.select_next();
+        assert_eq!(picker.enter(), None);
+        assert!(picker.dir.ends_with("sub"));
+        assert_eq!(names(&picker), vec![".."]);
+        picker.open_parent();
+        assert_eq!(picker.dir, dir.canonicalize().unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,15 +1,31 @@
 extern crate test;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, rc::Rc};
 
 use super::actions::{Action, TimedAction};
+use super::alignment::{align, AlignmentMode, ErrorCounts, Mark};
 use super::app_state::StateError;
+use super::correction::CorrectionPolicy;
+use super::ngrams::{most_error_prone, ngram_stats, slowest, Keystroke, NgramStats};
+use crate::clock::Clock;
 use crate::keyboard::Keyboard;
 
+// (total, valid) keystrokes for each target key
+pub type KeysStats = HashMap<char, (i32, i32)>;
+
 pub struct StateStats {
     pub aps: Result<f32, StateError>,
     pub precision: Option<f32>,
     pub words_per_minute: Option<f32>,
     pub keys_precision: Option<Vec<(char, f32)>>,
+    pub keys_stats: KeysStats,
+    pub elapsed: u128,
+    pub errors: usize,
+    pub error_kinds: ErrorCounts,
+    // Bigrams and trigrams of the text, then the ones to work on
+    pub ngrams: Vec<NgramStats>,
+    pub slowest_ngrams: Vec<NgramStats>,
+    pub error_prone_ngrams: Vec<NgramStats>,
 }
 #[derive(PartialEq, Eq)]
 pub enum ElementValue {
@@ -17,28 +33,114 @@ pub enum ElementValue {
     Newline,
 }
 
+impl ElementValue {
+    pub fn as_char(&self) -> char {
+        match self {
+            ElementValue::Char(c) => *c,
+            ElementValue::Newline => '\n',
+        }
+    }
+
+    // Words end on whitespace, including the end of a line
+    fn is_separator(&self) -> bool {
+        self.as_char().is_whitespace()
+    }
+}
+
 #[derive(PartialEq)]
 pub struct Element {
     pub target: ElementValue,
     pub value: Option<ElementValue>,
+    // Characters typed past the end of the word this separator ends, in edit distance mode
+    pub extra: Vec<char>,
+    // Keystrokes refused here by the correction policy
+    pub misses: usize,
+}
+
+impl Element {
+    fn positional_mark(&self) -> Option<Mark> {
+        match &self.value {
+            Some(v) if *v == self.target => Some(Mark::Correct),
+            Some(_) => Some(Mark::Substitution),
+            None => None,
+        }
+    }
 }
 
 pub struct Line(pub u8, pub Vec<Element>);
 
 pub type Cursor = (usize, usize);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestMode {
+    Full,
+    // Time limit in seconds, counted from the first typed character
+    Timed(u64),
+}
+
+pub const TIMED_DURATIONS: [u64; 4] = [15, 30, 60, 120];
+
+impl TestMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestMode::Full => "full",
+            TestMode::Timed(_) => "timed",
+        }
+    }
+
+    // Cycles full -> 15s -> 30s -> 60s -> 120s -> full
+    pub fn next(&self) -> TestMode {
+        match self {
+            TestMode::Full => TestMode::Timed(TIMED_DURATIONS[0]),
+            TestMode::Timed(seconds) => TIMED_DURATIONS
+                .iter()
+                .find(|d| *d > seconds)
+                .map_or(TestMode::Full, |d| TestMode::Timed(*d)),
+        }
+    }
+}
+
+impl fmt::Display for TestMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestMode::Full => write!(f, "full"),
+            TestMode::Timed(seconds) => write!(f, "timed-{}", seconds),
+        }
+    }
+}
+
 pub struct TypeTestState {
+    pub mode: TestMode,
+    pub alignment: AlignmentMode,
+    pub correction: CorrectionPolicy,
     pub cursor: Cursor,
     pub lines: Vec<Line>,
     pub actions: Vec<TimedAction>,
+    // Characters typed on the target, for the n-gram latencies
+    pub keystrokes: Vec<Keystroke>,
+    // Bumped by corrections and pauses, which break n-grams
+    pub run: u32,
     pub keyboard: Keyboard,
     pub last_modified: Option<Cursor>,
+    pub started_at: Option<u128>,
+    pub finished_at: Option<u128>,
+    pub paused_at: Option<u128>,
+    // Time spent in past pauses, left out of the elapsed time
+    pub paused: u128,
+    // Bumped whenever the text or the typed values are reset, so views can drop their caches.
+    pub generation: u64,
+    pub clock: Rc<dyn Clock>,
 }
 
 fn get_lines(target: String) -> Vec<Line> {
+    let target = target.trim_end();
+    let n_lines = target.split('\n').count();
+
     target
         .split('\n')
-        .map(|line| {
+        .enumerate()
+        .map(|(i, line)| {
             let mut trimmed_line = line.trim_start();
             let padding = line.len() - trimmed_line.len();
             trimmed_line = trimmed_line.trim_end();
@@ -48,23 +150,67 @@ fn get_lines(target: String) -> Vec<Line> {
                 elements.push(Element {
                     target: ElementValue::Char(c),
                     value: None,
+                    extra: Vec::new(),
+                    misses: 0,
+                });
+            }
+            // The last line has no trailing newline so the test ends on its last character
+            if i != n_lines - 1 || elements.is_empty() {
+                elements.push(Element {
+                    target: ElementValue::Newline,
+                    value: None,
+                    extra: Vec::new(),
+                    misses: 0,
                 });
             }
-            elements.push(Element {
-                target: ElementValue::Newline,
-                value: None,
-            });
             Line(padding as u8, elements)
         })
         .collect()
 }
 
+pub fn keys_precision(keys_stats: &KeysStats) -> Vec<(char, f32)> {
+    let mut keys_precision: Vec<_> = keys_stats
+        .iter()
+        .map(|(k, v)| {
+            let (total, valid) = v;
+            if *total != 0 {
+                (*k, *valid as f32 / *total as f32)
+            } else {
+                (*k, 0.0)
+            }
+        })
+        .collect();
+    keys_precision.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    keys_precision
+}
+
 enum CursorMoveDirection {
     Left,
     Right,
 }
 
 impl TypeTestState {
+    pub fn new(keyboard: Keyboard, clock: Rc<dyn Clock>) -> TypeTestState {
+        TypeTestState {
+            mode: TestMode::Full,
+            alignment: AlignmentMode::Strict,
+            correction: CorrectionPolicy::Free,
+            cursor: (0, 0),
+            lines: Vec::new(),
+            actions: Vec::new(),
+            keystrokes: Vec::new(),
+            run: 0,
+            keyboard,
+            last_modified: None,
+            started_at: None,
+            finished_at: None,
+            paused_at: None,
+            paused: 0,
+            generation: 0,
+            clock,
+        }
+    }
+
     fn move_curosr(&mut self, direction: CursorMoveDirection) {
         match direction {
             CursorMoveDirection::Left => {
@@ -90,12 +236,169 @@ impl TypeTestState {
         }
     }
 
+    fn is_last_element(&self, cursor: Cursor) -> bool {
+        match self.lines.last() {
+            Some(line) => cursor == (self.lines.len() - 1, line.1.len() - 1),
+            None => true,
+        }
+    }
+
+    // The target character under the cursor
+    pub fn expected_char(&self) -> Option<char> {
+        if self.is_finished() {
+            return None;
+        }
+        let (i, j) = self.cursor;
+        self.lines
+            .get(i)
+            .and_then(|line| line.1.get(j))
+            .map(|element| element.target.as_char())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    // Ended by the deadline rather than by typing
+    fn timed_out(&self) -> bool {
+        self.deadline()
+            .is_some_and(|deadline| self.finished_at == Some(deadline))
+    }
+
+    pub fn deadline(&self) -> Option<u128> {
+        match (self.mode, self.started_at) {
+            (TestMode::Timed(seconds), Some(started_at)) => {
+                Some(started_at + seconds as u128 * 1000 + self.paused)
+            }
+            _ => None,
+        }
+    }
+
+    // Milliseconds left in a timed test, None when the test isn't timed
+    pub fn remaining(&self, time: u128) -> Option<u128> {
+        match self.mode {
+            TestMode::Full => None,
+            TestMode::Timed(seconds) => Some(match self.deadline() {
+                Some(deadline) => {
+                    deadline.saturating_sub(self.finished_at.or(self.paused_at).unwrap_or(time))
+                }
+                None => seconds as u128 * 1000,
+            }),
+        }
+    }
+
+    // The end of the test so far: the finish time, the deadline or now, whichever comes first
+    fn end_time(&self) -> u128 {
+        let time = self
+            .finished_at
+            .or(self.paused_at)
+            .unwrap_or_else(|| self.clock.now());
+        self.deadline().map_or(time, |deadline| time.min(deadline))
+    }
+
+    // Typing time so far, pauses excluded
+    fn elapsed(&self) -> u128 {
+        match self.started_at {
+            Some(started_at) => self
+                .end_time()
+                .saturating_sub(started_at)
+                .saturating_sub(self.paused),
+            None => 0,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    fn toggle_pause(&mut self, time: u128) {
+        match self.paused_at.take() {
+            Some(paused_at) => self.paused += time.saturating_sub(paused_at),
+            // Nothing to pause before the first key
+            None if self.started_at.is_some() => self.paused_at = Some(time),
+            None => {}
+        }
+    }
+
+    pub fn tick(&mut self, time: u128) {
+        if self.is_finished() || self.is_paused() {
+            return;
+        }
+
+        if let Some(deadline) = self.deadline() {
+            if time >= deadline {
+                self.finished_at = Some(deadline);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for line in self.lines.iter_mut() {
+            for element in line.1.iter_mut() {
+                element.value = None;
+                element.extra.clear();
+                element.misses = 0;
+            }
+        }
+
+        self.cursor = (0, 0);
+        self.last_modified = None;
+        self.actions.clear();
+        self.keystrokes.clear();
+        self.run = 0;
+        self.started_at = None;
+        self.finished_at = None;
+        self.paused_at = None;
+        self.paused = 0;
+        self.generation += 1;
+    }
+
     pub fn dispatch(&mut self, action: TimedAction) {
+        if !matches!(action.action, Action::SetTarget(_)) {
+            // Input is locked once the time runs out
+            self.tick(action.time);
+            if self.is_finished() {
+                return;
+            }
+
+            if self.is_paused() && !matches!(action.action, Action::Pause) {
+                return;
+            }
+        }
+
         match &action.action {
             Action::Char(c) => {
                 self.last_modified = Some(self.cursor);
+                self.started_at.get_or_insert(action.time);
+
+                match self.expected_char() {
+                    Some(expected) if expected != *c => {
+                        self.keyboard.key_missed(expected, *c, action.time)
+                    }
+                    _ => self.keyboard.key_pressed(*c, action.time),
+                }
+                if let Some(expected) = self.expected_char() {
+                    self.keystrokes.push(Keystroke {
+                        expected,
+                        typed: *c,
+                        time: action.time,
+                        run: self.run,
+                    });
+                }
 
-                self.keyboard.key_pressed(*c);
+                if !self.accepts(*c) {
+                    let (i, j) = self.cursor;
+                    self.lines[i].1[j].misses += 1;
+                    self.actions.push(action);
+                    return;
+                }
+
+                if self.alignment == AlignmentMode::EditDistance
+                    && self.align_input(*c, action.time)
+                {
+                    self.actions.push(action);
+                    return;
+                }
 
                 let line = self.lines.get_mut(self.cursor.0).unwrap();
 
@@ -106,44 +409,246 @@ impl TypeTestState {
                     _ => element.value = Some(ElementValue::Char(*c)),
                 }
 
-                self.move_curosr(CursorMoveDirection::Right);
+                if self.is_last_element(self.cursor) {
+                    self.finished_at = Some(action.time);
+                } else {
+                    self.move_curosr(CursorMoveDirection::Right);
+                }
             }
 
             Action::Backspace => {
-                let element = self
-                    .lines
-                    .get_mut(self.cursor.0)
-                    .unwrap()
-                    .1
-                    .get_mut(self.cursor.1)
-                    .unwrap();
-                element.value = None;
+                self.run += 1;
+                if !self.backspace() {
+                    return;
+                }
+            }
 
-                self.last_modified = Some(self.cursor);
+            Action::DeleteWord => {
+                self.run += 1;
+                // Whitespace before the cursor goes along with the word before it
+                let mut in_word = false;
+                while let Some((i, j)) = self.previous_cursor() {
+                    let at_separator = self.lines[i].1[j].target.is_separator();
+                    if at_separator && in_word {
+                        break;
+                    }
+                    in_word |= !at_separator;
+                    if !self.backspace() {
+                        break;
+                    }
+                }
+            }
 
-                self.move_curosr(CursorMoveDirection::Left);
+            Action::ClearLine => {
+                self.run += 1;
+                while self.cursor.1 > 0 && self.backspace() {}
             }
 
+            Action::Pause => {
+                self.run += 1;
+                self.toggle_pause(action.time);
+                return;
+            }
+
+            // Handled by the app state
+            Action::Restart
+            | Action::Quit
+            | Action::Up
+            | Action::Down
+            | Action::Left
+            | Action::Right => return,
+
             Action::SetTarget(s) => {
                 // Trim each line then join and return vec char
                 self.lines = get_lines(s.to_string());
+                self.reset();
+                return;
             }
         }
 
         self.actions.push(action);
     }
 
+    // Marks the latest press of the key as released. Shift may be let go first, so the
+    // character is matched whatever its case.
+    pub fn release(&mut self, c: char, time: u128) {
+        let same_key = |typed: char| typed.to_lowercase().eq(c.to_lowercase());
+        let pressed = self.actions.iter_mut().rev().find(|a| {
+            matches!(a.action, Action::Char(typed) if same_key(typed)) && a.released.is_none()
+        });
+        if let Some(action) = pressed.filter(|a| a.time <= time) {
+            action.released = Some(time);
+        }
+    }
+
+    // Returns false when the policy refuses it or there is nothing to delete
+    fn backspace(&mut self) -> bool {
+        if self.cursor == (0, 0) || !self.can_backspace() {
+            return false;
+        }
+
+        self.last_modified = Some(self.cursor);
+
+        let (i, j) = self.cursor;
+        if self.lines[i].1[j].extra.pop().is_none() {
+            self.move_curosr(CursorMoveDirection::Left);
+
+            let (i, mut j) = self.cursor;
+            let line = &mut self.lines[i].1;
+            line[j].value = None;
+
+            // Characters skipped by ending a word early are not typed again
+            while j > 0 && line[j - 1].value.is_none() && !line[j - 1].target.is_separator() {
+                j -= 1;
+            }
+            self.cursor.1 = j;
+        }
+
+        true
+    }
+
+    fn previous_cursor(&self) -> Option<Cursor> {
+        match self.cursor {
+            (0, 0) => None,
+            (i, 0) => Some((i - 1, self.lines[i - 1].1.len() - 1)),
+            (i, j) => Some((i, j - 1)),
+        }
+    }
+
+    // Whether the word ending at `end`, a separator, was typed without mistakes so far
+    fn word_is_correct(&self, (i, end): Cursor) -> bool {
+        let line = &self.lines[i].1;
+        line[end].extra.is_empty()
+            && line[..end]
+                .iter()
+                .rev()
+                .take_while(|e| !e.target.is_separator())
+                .all(|e| e.value.as_ref() == Some(&e.target))
+    }
+
+    // Whether the correction policy lets `c` be typed at the cursor
+    fn accepts(&self, c: char) -> bool {
+        let target = &self.lines[self.cursor.0].1[self.cursor.1].target;
+
+        match self.correction {
+            CorrectionPolicy::StopOnError => target.as_char() == c,
+            CorrectionPolicy::StopOnWordError if target.is_separator() => {
+                target.as_char() == c && self.word_is_correct(self.cursor)
+            }
+            // Ending the word early would leave it wrong
+            CorrectionPolicy::StopOnWordError => {
+                !(c.is_whitespace() && self.alignment == AlignmentMode::EditDistance)
+            }
+            _ => true,
+        }
+    }
+
+    fn can_backspace(&self) -> bool {
+        let (i, j) = self.cursor;
+        if !self.lines[i].1[j].extra.is_empty() {
+            return self.correction != CorrectionPolicy::Disabled;
+        }
+
+        let Some(previous) = self.previous_cursor() else {
+            return true;
+        };
+        let previous_element = &self.lines[previous.0].1[previous.1];
+
+        match self.correction {
+            CorrectionPolicy::Disabled => false,
+            CorrectionPolicy::WithinWord => !previous_element.target.is_separator(),
+            CorrectionPolicy::NoCorrectWord => {
+                !previous_element.target.is_separator()
+                    || previous_element.value.as_ref() != Some(&previous_element.target)
+                    || !self.word_is_correct(previous)
+            }
+            _ => true,
+        }
+    }
+
+    // Keeps the cursor in step with the words when a word is cut short or overrun.
+    // Returns true when the character was handled and must not be stored positionally.
+    fn align_input(&mut self, c: char, time: u128) -> bool {
+        let (i, j) = self.cursor;
+        let line = &mut self.lines[i].1;
+        let at_separator = line[j].target.is_separator();
+        let after_word = j > 0 && !line[j - 1].target.is_separator();
+
+        if c.is_whitespace() && !at_separator {
+            if !after_word {
+                // Nothing of the word is typed yet, don't skip it entirely
+                return true;
+            }
+            match (j..line.len()).find(|k| line[*k].target.is_separator()) {
+                // The rest of the word is omitted, the separator is typed as usual
+                Some(k) => self.cursor.1 = k,
+                None => {
+                    // Last word of the text
+                    self.finished_at = Some(time);
+                    return true;
+                }
+            }
+        } else if !c.is_whitespace() && at_separator && after_word {
+            line[j].extra.push(c);
+            return true;
+        }
+
+        false
+    }
+
+    // Marks of each element of a line, and the number of extra characters typed in the
+    // word ending just before each element (the last entry is for the end of the line).
+    pub fn align_line(&self, i: usize) -> (Vec<Option<Mark>>, Vec<usize>) {
+        let line = &self.lines[i].1;
+        let mut marks: Vec<Option<Mark>> = line.iter().map(|e| e.positional_mark()).collect();
+        let mut insertions = vec![0; line.len() + 1];
+
+        if self.alignment == AlignmentMode::Strict {
+            return (marks, insertions);
+        }
+
+        let mut start = 0;
+        while start < line.len() {
+            let end = (start..line.len())
+                .find(|k| line[*k].target.is_separator())
+                .unwrap_or(line.len());
+
+            // Until then the word is compared position by position
+            let complete = match line.get(end) {
+                Some(separator) => separator.value.is_some(),
+                None => self.is_finished() && !self.timed_out(),
+            };
+
+            if complete && end > start {
+                let word = &line[start..end];
+                let target: Vec<char> = word.iter().map(|e| e.target.as_char()).collect();
+                let mut typed: Vec<char> = word
+                    .iter()
+                    .filter_map(|e| e.value.as_ref().map(|v| v.as_char()))
+                    .collect();
+                if let Some(separator) = line.get(end) {
+                    typed.extend(&separator.extra);
+                }
+
+                let (word_marks, word_insertions) = align(&target, &typed);
+                for (k, mark) in word_marks.into_iter().enumerate() {
+                    marks[start + k] = Some(mark);
+                }
+                insertions[end] = word_insertions;
+            }
+
+            start = end + 1;
+        }
+
+        (marks, insertions)
+    }
+
     pub fn actions_per_seconds(&self) -> Result<f32, StateError> {
         if self.actions.is_empty() {
             return Err(StateError::NoActionYet);
         }
 
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-
-        let elapsed = time - self.actions.first().unwrap().time;
+        let elapsed = self.elapsed();
 
         if elapsed == 0 {
             return Err(StateError::NoActionYet);
@@ -155,58 +660,78 @@ impl TypeTestState {
     }
 
     pub fn stats(&self) -> StateStats {
-        let time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis();
-
-        let elasped = match self.actions.first() {
-            Some(a) => time - a.time,
-            None => 0,
-        };
+        let elasped = self.elapsed();
 
         let mut valid = 0;
         let mut total = 0;
-        let mut keys_stats: HashMap<char, (i32, i32)> = HashMap::new();
+        let mut keys_stats: KeysStats = HashMap::new();
+        let mut error_kinds = ErrorCounts::default();
 
-        'outer: for line in &self.lines {
-            for element in &line.1 {
-                let key = match &element.target {
-                    ElementValue::Char(c) => c,
-                    ElementValue::Newline => &'\n',
-                };
+        if self.alignment == AlignmentMode::Strict {
+            'outer: for line in &self.lines {
+                for element in &line.1 {
+                    let key = match &element.target {
+                        ElementValue::Char(c) => c,
+                        ElementValue::Newline => &'\n',
+                    };
 
-                match (&element.target, &element.value) {
-                    (a, Some(b)) if a == b => {
-                        valid += 1;
-                        keys_stats
-                            .entry(*key)
-                            .and_modify(|e| e.1 += 1)
-                            .or_insert((0, 1));
+                    if element.misses > 0 {
+                        total += element.misses;
+                        keys_stats.entry(*key).or_insert((0, 0)).0 += element.misses as i32;
                     }
-                    (_, None) => break 'outer,
-                    _ => {}
+
+                    match (&element.target, &element.value) {
+                        (a, Some(b)) if a == b => {
+                            valid += 1;
+                            keys_stats
+                                .entry(*key)
+                                .and_modify(|e| e.1 += 1)
+                                .or_insert((0, 1));
+                        }
+                        (_, None) => break 'outer,
+                        _ => {}
+                    }
+                    total += 1;
+                    keys_stats
+                        .entry(*key)
+                        .and_modify(|e| e.0 += 1)
+                        .or_insert((1, 0));
                 }
-                total += 1;
-                keys_stats
-                    .entry(*key)
-                    .and_modify(|e| e.0 += 1)
-                    .or_insert((1, 0));
             }
-        }
 
-        let mut keys_precision: Vec<_> = keys_stats
-            .iter()
-            .map(|(k, v)| {
-                let (total, valid) = v;
-                if *total != 0 {
-                    (*k, *valid as f32 / *total as f32)
-                } else {
-                    (*k, 0.0)
+            error_kinds.substitutions = total - valid;
+        } else {
+            for i in 0..self.lines.len() {
+                let (marks, insertions) = self.align_line(i);
+                error_kinds.insertions += insertions.iter().sum::<usize>();
+
+                for (element, mark) in self.lines[i].1.iter().zip(marks) {
+                    if mark.is_none() && element.misses == 0 {
+                        continue;
+                    }
+                    let entry = keys_stats.entry(element.target.as_char()).or_insert((0, 0));
+                    entry.0 += element.misses as i32;
+                    total += element.misses;
+
+                    let Some(mark) = mark else {
+                        continue;
+                    };
+                    entry.0 += 1;
+                    total += 1;
+
+                    match mark {
+                        Mark::Correct => {
+                            entry.1 += 1;
+                            valid += 1;
+                        }
+                        Mark::Substitution => error_kinds.substitutions += 1,
+                        Mark::Omission => error_kinds.omissions += 1,
+                        Mark::Transposition => error_kinds.transpositions += 1,
+                    }
                 }
-            })
-            .collect();
-        keys_precision.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            }
+            total += error_kinds.insertions;
+        }
 
         let precision = if valid == 0 {
             None
@@ -220,11 +745,20 @@ impl TypeTestState {
             Some(total as f32 / (elasped) as f32 * 1000.0 * 60.0 / 5.0)
         };
 
+        let ngrams = ngram_stats(&self.keystrokes);
+
         StateStats {
             aps: self.actions_per_seconds(),
             precision,
             words_per_minute,
-            keys_precision: Some(keys_precision),
+            keys_precision: Some(keys_precision(&keys_stats)),
+            keys_stats,
+            elapsed: elasped,
+            errors: total - valid,
+            error_kinds,
+            slowest_ngrams: slowest(&ngrams, 10),
+            error_prone_ngrams: most_error_prone(&ngrams, 10),
+            ngrams,
             // precision: Some(valid as f32),
             // words_per_minute: Some(total as f32),
         }
@@ -235,28 +769,344 @@ impl TypeTestState {
 mod tests {
 
     use super::*;
+    use crate::clock::ManualClock;
     use test::Bencher;
 
+    fn new_state_with_clock(target: &str, clock: Rc<ManualClock>) -> TypeTestState {
+        let mut state = TypeTestState::new(
+            crate::keyboard::LayoutSource::Builtin(String::from("qwerty"))
+                .load()
+                .unwrap(),
+            clock,
+        );
+        state.dispatch(TimedAction {
+            action: Action::SetTarget(target.to_string()),
+            time: 0,
+            released: None,
+        });
+        state
+    }
+
+    fn new_state(target: &str) -> TypeTestState {
+        new_state_with_clock(target, Rc::new(ManualClock::new()))
+    }
+
+    fn type_str(state: &mut TypeTestState, s: &str, time: u128) {
+        for c in s.chars() {
+            state.dispatch(TimedAction {
+                action: Action::Char(c),
+                time,
+                released: None,
+            });
+        }
+    }
+
+    #[test]
+    fn shows_the_expected_key_after_a_mistake() {
+        let mut state = new_state("ab cd");
+        assert_eq!(state.expected_char(), Some('a'));
+
+        type_str(&mut state, "a", 1000);
+        assert_eq!(state.keyboard.active_keys.len(), 1);
+        assert_eq!(state.expected_char(), Some('b'));
+
+        type_str(&mut state, "x", 1100);
+        let b = state.keyboard.key_for('b').unwrap();
+        let x = state.keyboard.key_for('x').unwrap();
+        assert!(state.keyboard.missed_keys.contains_key(&b));
+        assert!(state.keyboard.wrong_keys.contains_key(&x));
+
+        type_str(&mut state, " cd", 1200);
+        assert_eq!(state.expected_char(), None);
+    }
+
+    #[test]
+    fn releases_mark_the_latest_press() {
+        let mut state = new_state("aab");
+        type_str(&mut state, "a", 1000);
+        type_str(&mut state, "a", 1100);
+        state.release('a', 1150);
+        type_str(&mut state, "B", 1200);
+        // Shift let go before the key
+        state.release('b', 1300);
+        state.release('a', 1400);
+
+        let released: Vec<_> = state.actions.iter().map(|a| a.released).collect();
+        assert_eq!(released, vec![Some(1400), Some(1150), Some(1300)]);
+    }
+
+    #[test]
+    fn corrections_break_ngrams() {
+        let mut state = new_state("abab");
+        type_str(&mut state, "a", 1000);
+        type_str(&mut state, "x", 1100);
+        backspace(&mut state);
+        type_str(&mut state, "b", 1200);
+        type_str(&mut state, "a", 1300);
+        type_str(&mut state, "b", 1400);
+
+        let stats = state.stats();
+        let ab = stats.ngrams.iter().find(|s| s.ngram == "ab").unwrap();
+        assert_eq!((ab.count, ab.errors), (2, 1));
+        assert_eq!(ab.mean_latency(), Some(100.0));
+        // "abb" would span the correction
+        assert!(stats.ngrams.iter().all(|s| s.ngram != "abb"));
+
+        state.reset();
+        assert!(state.keystrokes.is_empty());
+    }
+
+    #[test]
+    fn finishes_on_last_element() {
+        let mut state = new_state("ab\ncd\n");
+        type_str(&mut state, "ab\nc", 1000);
+        assert!(!state.is_finished());
+
+        type_str(&mut state, "x", 3000);
+        assert_eq!(state.finished_at, Some(3000));
+
+        let stats = state.stats();
+        assert_eq!(stats.elapsed, 2000);
+        assert_eq!(stats.errors, 1);
+
+        // Input after the end is ignored
+        type_str(&mut state, "d", 4000);
+        assert_eq!(state.actions.len(), 5);
+    }
+
+    #[test]
+    fn timed_test_locks_input_at_deadline() {
+        let mut state = new_state("abcdef");
+        state.mode = TestMode::Timed(15);
+        assert_eq!(state.remaining(0), Some(15000));
+
+        type_str(&mut state, "ab", 1000);
+        state.tick(10000);
+        assert_eq!(state.remaining(10000), Some(6000));
+        assert!(!state.is_finished());
+
+        type_str(&mut state, "c", 16000);
+        assert_eq!(state.finished_at, Some(16000));
+        assert_eq!(state.actions.len(), 2);
+
+        let stats = state.stats();
+        assert_eq!(stats.elapsed, 15000);
+        assert_eq!(stats.precision, Some(1.0));
+    }
+
+    #[test]
+    fn stats_follow_the_clock() {
+        let clock = Rc::new(ManualClock::new());
+        let mut state = new_state_with_clock("abcdefghij", clock.clone());
+
+        clock.set(5000);
+        assert_eq!(state.stats().elapsed, 0);
+
+        type_str(&mut state, "abcde", 6000);
+        clock.set(18000);
+        let stats = state.stats();
+        assert_eq!(stats.elapsed, 12000);
+        assert!((stats.words_per_minute.unwrap() - 5.0).abs() < 1e-3);
+
+        // A clock behind the recorded actions doesn't underflow
+        clock.set(0);
+        assert_eq!(state.stats().elapsed, 0);
+    }
+
+    #[test]
+    fn test_mode_cycles() {
+        let mut mode = TestMode::Full;
+        for expected in ["timed-15", "timed-30", "timed-60", "timed-120", "full"] {
+            mode = mode.next();
+            assert_eq!(mode.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn reset_clears_values() {
+        let mut state = new_state("ab");
+        type_str(&mut state, "ab", 1000);
+        assert!(state.is_finished());
+
+        state.reset();
+        assert!(!state.is_finished());
+        assert_eq!(state.cursor, (0, 0));
+        assert!(state.lines[0].1.iter().all(|e| e.value.is_none()));
+    }
+
+    #[test]
+    fn edit_distance_resyncs_words() {
+        let mut state = new_state("hello world\nbye");
+        state.alignment = AlignmentMode::EditDistance;
+
+        // Omission, then an insertion past the end of the word
+        type_str(&mut state, "helo ", 1000);
+        assert_eq!(state.cursor, (0, 6));
+        type_str(&mut state, "worldd\n", 2000);
+        assert_eq!(state.cursor, (1, 0));
+        assert_eq!(state.lines[0].1[11].extra, vec!['d']);
+
+        type_str(&mut state, "bey", 3000);
+        assert!(state.is_finished());
+
+        let stats = state.stats();
+        assert_eq!(stats.error_kinds.omissions, 1);
+        assert_eq!(stats.error_kinds.insertions, 1);
+        assert_eq!(stats.error_kinds.transpositions, 2);
+        assert_eq!(stats.error_kinds.substitutions, 0);
+        assert_eq!(stats.errors, 4);
+
+        // The same keystrokes shift the rest of the word in strict mode
+        let mut strict = new_state("hello world");
+        type_str(&mut strict, "helo w", 1000);
+        assert_eq!(strict.stats().error_kinds.substitutions, 3);
+    }
+
+    #[test]
+    fn backspace_removes_extra_characters_first() {
+        let mut state = new_state("ab cd");
+        state.alignment = AlignmentMode::EditDistance;
+        type_str(&mut state, "abx", 1000);
+        assert_eq!(state.cursor, (0, 2));
+
+        state.dispatch(TimedAction {
+            action: Action::Backspace,
+            time: 1000,
+            released: None,
+        });
+        assert!(state.lines[0].1[2].extra.is_empty());
+        assert_eq!(state.cursor, (0, 2));
+    }
+
+    fn backspace(state: &mut TypeTestState) {
+        state.dispatch(TimedAction {
+            action: Action::Backspace,
+            time: 1000,
+            released: None,
+        });
+    }
+
+    #[test]
+    fn backspace_clears_the_previous_element() {
+        let mut state = new_state("ab\ncd");
+        type_str(&mut state, "ab\n", 1000);
+        backspace(&mut state);
+        assert_eq!(state.cursor, (0, 2));
+        assert!(state.lines[0].1[2].value.is_none());
+
+        backspace(&mut state);
+        assert_eq!(state.cursor, (0, 1));
+        assert!(state.lines[0].1[1].value.is_none());
+    }
+
+    #[test]
+    fn correction_policies_limit_backspace() {
+        let typed = |correction| {
+            let mut state = new_state("ab cd ef");
+            state.correction = correction;
+            type_str(&mut state, "ab cx", 1000);
+            backspace(&mut state);
+            backspace(&mut state);
+            backspace(&mut state);
+            state.cursor
+        };
+
+        assert_eq!(typed(CorrectionPolicy::Free), (0, 2));
+        assert_eq!(typed(CorrectionPolicy::Disabled), (0, 5));
+        assert_eq!(typed(CorrectionPolicy::WithinWord), (0, 3));
+        // "ab" was typed correctly
+        assert_eq!(typed(CorrectionPolicy::NoCorrectWord), (0, 3));
+    }
+
+    #[test]
+    fn stop_on_error_counts_refused_keys() {
+        let mut state = new_state("ab cd");
+        state.correction = CorrectionPolicy::StopOnError;
+        type_str(&mut state, "axb", 1000);
+        assert_eq!(state.cursor, (0, 2));
+        assert_eq!(state.lines[0].1[1].misses, 1);
+
+        let stats = state.stats();
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.keys_stats[&'b'], (2, 1));
+
+        let mut state = new_state("ab cd");
+        state.correction = CorrectionPolicy::StopOnWordError;
+        type_str(&mut state, "ax ", 1000);
+        assert_eq!(state.cursor, (0, 2));
+        backspace(&mut state);
+        type_str(&mut state, "b ", 1000);
+        assert_eq!(state.cursor, (0, 3));
+    }
+
+    #[test]
+    fn deletes_words_and_lines() {
+        let mut state = new_state("ab cd ef\ngh");
+        let dispatch = |state: &mut TypeTestState, action| {
+            state.dispatch(TimedAction {
+                action,
+                time: 1000,
+                released: None,
+            })
+        };
+
+        type_str(&mut state, "ab cd e", 1000);
+        dispatch(&mut state, Action::DeleteWord);
+        assert_eq!(state.cursor, (0, 6));
+        dispatch(&mut state, Action::DeleteWord);
+        assert_eq!(state.cursor, (0, 3));
+        assert!(state.lines[0].1[3..].iter().all(|e| e.value.is_none()));
+
+        type_str(&mut state, "cd ef\ng", 1000);
+        dispatch(&mut state, Action::ClearLine);
+        assert_eq!(state.cursor, (1, 0));
+        dispatch(&mut state, Action::ClearLine);
+        assert_eq!(state.cursor, (1, 0));
+    }
+
+    #[test]
+    fn pauses_are_not_timed() {
+        let clock = Rc::new(ManualClock::new());
+        let mut state = new_state_with_clock("abcdef", clock.clone());
+        state.mode = TestMode::Timed(15);
+        let pause = |state: &mut TypeTestState, time| {
+            state.dispatch(TimedAction {
+                action: Action::Pause,
+                time,
+                released: None,
+            })
+        };
+
+        type_str(&mut state, "ab", 1000);
+        pause(&mut state, 3000);
+        type_str(&mut state, "cd", 4000);
+        assert_eq!(state.cursor, (0, 2));
+
+        clock.set(10000);
+        assert_eq!(state.stats().elapsed, 2000);
+        assert_eq!(state.remaining(10000), Some(13000));
+
+        pause(&mut state, 20000);
+        state.tick(25000);
+        assert!(!state.is_finished());
+        assert_eq!(state.remaining(25000), Some(8000));
+    }
+
     #[bench]
     fn bench_pow(b: &mut Bencher) {
-        let mut state = TypeTestState {
-            cursor: (0, 0),
-            lines: get_lines((0..500).map(|_| 'a').collect::<String>()),
-            actions: Vec::new(),
-            keyboard: Keyboard::new("layout.txt".to_string()).unwrap(),
-            last_modified: None,
-        };
+        let clock = Rc::new(ManualClock::new());
+        let mut state =
+            new_state_with_clock(&(0..500).map(|_| 'a').collect::<String>(), clock.clone());
 
         b.iter(|| {
             // Inner closure, the actual test
             for _ in 0..1000 {
-                let time = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis();
+                let time = clock.now() + 10;
+                clock.set(time);
                 let action = TimedAction {
                     time,
                     action: Action::Char('a'),
+                    released: None,
                 };
                 state.stats();
                 state.dispatch(action);
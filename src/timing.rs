@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::state::{Action, TimedAction, IDLE_LATENCY};
+
+// How long keys are held and the gaps between them. Only terminals reporting key releases
+// give these.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimingReport {
+    // Mean time from the press of a key to its release
+    pub dwell: Option<f32>,
+    // Mean time from the release of a key to the press of the next one, negative when the
+    // next key was pressed first
+    pub flight: Option<f32>,
+    // Consecutive keystrokes and how many of them overlapped
+    pub pairs: u32,
+    pub overlaps: u32,
+    // Mean dwell of each character, sorted by character
+    pub keys: Vec<(char, f32)>,
+}
+
+impl TimingReport {
+    pub fn new(actions: &[TimedAction]) -> Option<TimingReport> {
+        let mut dwell: HashMap<char, (u128, u32)> = HashMap::new();
+        let mut flight: (i128, u32) = (0, 0);
+        let mut report = TimingReport::default();
+        let mut previous: Option<&TimedAction> = None;
+
+        for action in actions {
+            let Action::Char(c) = action.action else {
+                previous = None;
+                continue;
+            };
+
+            if let Some(released) = action.released {
+                let entry = dwell.entry(c).or_insert((0, 0));
+                entry.0 += released.saturating_sub(action.time);
+                entry.1 += 1;
+            }
+
+            let pressed_after =
+                previous.filter(|p| action.time.saturating_sub(p.time) <= IDLE_LATENCY);
+            if let Some(released) = pressed_after.and_then(|p| p.released) {
+                flight.0 += action.time as i128 - released as i128;
+                flight.1 += 1;
+                report.pairs += 1;
+                if action.time < released {
+                    report.overlaps += 1;
+                }
+            }
+            previous = Some(action);
+        }
+
+        if dwell.is_empty() {
+            return None;
+        }
+
+        let (total, count) = dwell
+            .values()
+            .fold((0, 0), |(total, count), (t, c)| (total + t, count + c));
+        report.dwell = Some(total as f32 / count as f32);
+        report.flight = (flight.1 > 0).then(|| flight.0 as f32 / flight.1 as f32);
+        report.keys = dwell
+            .into_iter()
+            .map(|(c, (total, count))| (c, total as f32 / count as f32))
+            .collect();
+        report.keys.sort_by_key(|(c, _)| *c);
+        Some(report)
+    }
+
+    pub fn overlap_rate(&self) -> Option<f32> {
+        (self.pairs > 0).then(|| self.overlaps as f32 / self.pairs as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(c: char, time: u128, released: Option<u128>) -> TimedAction {
+        TimedAction {
+            action: Action::Char(c),
+            time,
+            released,
+        }
+    }
+
+    #[test]
+    fn measures_dwell_and_flight() {
+        // "b" is pressed before "a" is released, the backspace breaks the pairs
+        let actions = vec![
+            pressed('a', 0, Some(100)),
+            pressed('b', 80, Some(150)),
+            pressed('a', 250, Some(330)),
+            TimedAction {
+                action: Action::Backspace,
+                time: 400,
+                released: None,
+            },
+            pressed('c', 500, None),
+        ];
+
+        let report = TimingReport::new(&actions).unwrap();
+        assert_eq!(report.dwell, Some(250.0 / 3.0));
+        assert_eq!(report.flight, Some(40.0));
+        assert_eq!(report.overlap_rate(), Some(0.5));
+        assert_eq!(report.keys, vec![('a', 90.0), ('b', 70.0)]);
+
+        // Terminals without release events
+        assert_eq!(TimingReport::new(&[pressed('a', 0, None)]), None);
+    }
+
+    #[test]
+    fn tolerates_out_of_order_times() {
+        let actions = vec![pressed('a', 500, Some(600)), pressed('b', 100, Some(180))];
+
+        let report = TimingReport::new(&actions).unwrap();
+        assert_eq!(report.dwell, Some(90.0));
+        assert_eq!(report.flight, Some(-500.0));
+        assert_eq!(report.overlap_rate(), Some(1.0));
+    }
+}
@@ -1,19 +1,38 @@
-use std::vec;
+use std::{collections::HashMap, vec};
+
+use chrono::{Local, TimeZone};
 
 use ratatui::{
     prelude::{Backend, Constraint, Direction, Layout, Rect},
-    style::{Color, Style, Stylize},
+    style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Cell, Padding, Paragraph, Row, Table, Wrap},
+    widgets::{Block, Borders, Cell, Clear, Padding, Paragraph, Row, Table, TableState, Wrap},
     Frame,
 };
 
 use crate::{
-    keyboard::Keyboard,
-    state::{self, ElementValue},
+    fingers::{FingerReport, Workload},
+    heatmap::{self, HeatmapMetric, HeatmapScope},
+    keyboard::{Guide, Keyboard},
+    layout::Hand,
+    playlist::{Playlist, Summary},
+    source::TextSource,
+    state::{
+        self, AlignmentMode, ElementValue, HomeState, Mark, MenuItem, NgramStats, StateStats, View,
+    },
+    timing::TimingReport,
 };
 use state::State;
 
+// Heatmap buckets, from the best to the worst
+const HEAT_COLORS: [Color; 5] = [
+    Color::Green,
+    Color::LightGreen,
+    Color::Yellow,
+    Color::LightRed,
+    Color::Red,
+];
+
 fn centered_rect(r: Rect, percent_x: u16, percent_y: u16) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -34,27 +53,317 @@ fn centered_rect(r: Rect, percent_x: u16, percent_y: u16) -> Rect {
         .split(popup_layout[1])[1]
 }
 
+fn format_elapsed(elapsed: u128) -> String {
+    let seconds = elapsed / 1000;
+    format!(
+        "{}:{:02}.{}",
+        seconds / 60,
+        seconds % 60,
+        (elapsed % 1000) / 100
+    )
+}
+
+fn stat_line<'a>(label: &'a str, value: String) -> Line<'a> {
+    Line::from(vec![
+        Span::styled(label, Style::default().fg(Color::White)),
+        Span::styled(value, Style::default().fg(Color::Green)),
+    ])
+}
+
+fn stats_lines(stats: &StateStats) -> Vec<Line<'_>> {
+    let aps = stats
+        .aps
+        .as_ref()
+        .map_or(String::from("Undefined"), |x| x.to_string());
+    let words_per_minute = stats
+        .words_per_minute
+        .map_or(String::from("Undefined"), |x| x.to_string());
+    let precision = stats
+        .precision
+        .map_or(String::from("Undefined"), |x| (x * 100.0).to_string());
+
+    vec![
+        stat_line("APS: ", aps),
+        stat_line("WPM: ", words_per_minute),
+        stat_line("Precision: ", precision),
+    ]
+}
+
+// Also serves as the legend of the colors used in the text
+fn error_kind_lines(stats: &StateStats) -> Vec<Line<'_>> {
+    let kinds = &stats.error_kinds;
+    [
+        ("Substitutions: ", kinds.substitutions, Color::Red),
+        ("Omissions: ", kinds.omissions, Color::Yellow),
+        ("Insertions: ", kinds.insertions, Color::Magenta),
+        ("Transpositions: ", kinds.transpositions, Color::Cyan),
+    ]
+    .into_iter()
+    .map(|(label, count, color)| {
+        Line::from(vec![
+            Span::styled(label, Style::default().fg(color)),
+            Span::styled(count.to_string(), Style::default().fg(Color::White)),
+        ])
+    })
+    .collect()
+}
+
+fn percent(value: Option<f32>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.0}%", v * 100.0))
+}
+
+fn hand_name(hand: Hand) -> &'static str {
+    match hand {
+        Hand::Left => "left hand",
+        Hand::Right => "right hand",
+    }
+}
+
+fn finger_summary_lines(report: &FingerReport) -> Vec<Line<'static>> {
+    let hands: Vec<String> = report
+        .hands
+        .iter()
+        .map(|(hand, workload)| format!("{} {}", hand_name(*hand), percent(report.share(workload))))
+        .collect();
+    vec![
+        stat_line("Hands: ", hands.join(", ")),
+        stat_line("Alternation: ", percent(report.alternation_rate())),
+        stat_line("Same finger: ", percent(report.same_finger_rate())),
+    ]
+}
+
+fn millis(value: Option<f32>) -> String {
+    value.map_or(String::from("-"), |v| format!("{:.0} ms", v))
+}
+
+// Empty when the terminal doesn't report key releases
+fn timing_lines(report: Option<&TimingReport>) -> Vec<Line<'static>> {
+    let Some(report) = report else {
+        return Vec::new();
+    };
+    vec![
+        stat_line("Dwell: ", millis(report.dwell)),
+        stat_line(
+            "Flight: ",
+            format!(
+                "{} ({} rollover)",
+                millis(report.flight),
+                percent(report.overlap_rate())
+            ),
+        ),
+    ]
+}
+
+// One row per finger then per hand, so an overloaded pinky stands out
+fn finger_table(report: &FingerReport) -> Table<'static> {
+    let row = |name: &'static str, workload: &Workload| {
+        let accuracy = workload.accuracy();
+        let style = match accuracy {
+            Some(a) if a > 0.9 => Style::default().fg(Color::Green),
+            Some(a) if a > 0.8 => Style::default().fg(Color::Yellow),
+            Some(_) => Style::default().fg(Color::Red),
+            None => Style::default().fg(Color::White),
+        };
+        Row::new(vec![
+            Cell::from(name),
+            Cell::from(percent(report.share(workload))),
+            Cell::from(Span::styled(percent(accuracy), style)),
+            Cell::from(
+                workload
+                    .mean_interval()
+                    .map_or(String::from("-"), |i| format!("{:.0} ms", i)),
+            ),
+        ])
+    };
+
+    let rows: Vec<Row<'static>> = report
+        .fingers
+        .iter()
+        .map(|(finger, workload)| row(finger.name(), workload))
+        .chain(
+            report
+                .hands
+                .iter()
+                .map(|(hand, workload)| row(hand_name(*hand), workload)),
+        )
+        .collect();
+
+    Table::new(rows)
+        .header(
+            Row::new(vec!["Finger", "Share", "Accuracy", "Interval"])
+                .style(Style::default().fg(Color::Blue)),
+        )
+        .style(Style::default().fg(Color::White))
+        .widths(&[
+            Constraint::Length(13),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(9),
+        ])
+}
+
+// The slowest n-grams then the most error-prone ones
+fn ngram_table(stats: &StateStats) -> Table<'static> {
+    let section = |title: &'static str| {
+        Row::new(vec![Cell::from(title)]).style(Style::default().fg(Color::Blue))
+    };
+    let row = |s: &NgramStats| {
+        Row::new(vec![
+            Cell::from(s.ngram.clone()),
+            Cell::from(s.count.to_string()),
+            Cell::from(percent(Some(s.error_rate()))),
+            Cell::from(
+                s.mean_latency()
+                    .map_or(String::from("-"), |l| format!("{:.0} ms", l)),
+            ),
+        ])
+    };
+
+    let rows: Vec<Row<'static>> = std::iter::once(section("Slowest"))
+        .chain(stats.slowest_ngrams.iter().map(row))
+        .chain(std::iter::once(section("Most errors")))
+        .chain(stats.error_prone_ngrams.iter().map(row))
+        .collect();
+
+    Table::new(rows)
+        .header(
+            Row::new(vec!["N-gram", "Typed", "Errors", "Latency"])
+                .style(Style::default().fg(Color::Blue)),
+        )
+        .style(Style::default().fg(Color::White))
+        .widths(&[
+            Constraint::Length(12),
+            Constraint::Length(6),
+            Constraint::Length(7),
+            Constraint::Length(9),
+        ])
+}
+
+// Each file of a finished playlist, then all of them
+fn playlist_table(playlist: &Playlist, summary: &Summary) -> Table<'static> {
+    let wpm = |wpm: Option<f32>| wpm.map_or(String::from("-"), |w| format!("{:.1}", w));
+    let rows: Vec<Row<'static>> = playlist
+        .segments
+        .iter()
+        .map(|s| {
+            Row::new(vec![
+                s.source.clone(),
+                wpm(s.words_per_minute),
+                percent(s.precision),
+                format_elapsed(s.elapsed),
+            ])
+        })
+        .chain(std::iter::once(
+            Row::new(vec![
+                String::from("Total"),
+                wpm(summary.words_per_minute),
+                percent(summary.precision),
+                format_elapsed(summary.elapsed),
+            ])
+            .style(Style::default().fg(Color::Green)),
+        ))
+        .collect();
+
+    Table::new(rows)
+        .header(
+            Row::new(vec!["File", "WPM", "Accuracy", "Time"])
+                .style(Style::default().fg(Color::Blue)),
+        )
+        .style(Style::default().fg(Color::White))
+        .widths(&[
+            Constraint::Min(10),
+            Constraint::Length(6),
+            Constraint::Length(9),
+            Constraint::Length(8),
+        ])
+}
+
+fn keys_precision_table(stats: &StateStats) -> Option<Table<'_>> {
+    // sorted keys
+
+    let key_precision = stats.keys_precision.as_ref().map(|keys_prec| {
+        let x = keys_prec
+            .iter()
+            .map(|(k, v)| {
+                let mut style = Style::default().fg(Color::White);
+                if *v > 0.9 {
+                    style = style.fg(Color::Green);
+                } else if *v > 0.8 {
+                    style = style.fg(Color::Yellow);
+                } else {
+                    style = style.fg(Color::Red);
+                }
+                // format v with 3 decimal places
+                let e = format!("{:.3}", *v);
+
+                let p = Span::styled(e, style);
+                let line = Line::from(vec![
+                    Span::styled(k.to_string(), Style::default().fg(Color::White)),
+                    Span::styled(": ", Style::default().fg(Color::White)),
+                    p,
+                ]);
+                Cell::from(line)
+            })
+            .collect::<Vec<Cell<'_>>>();
+
+        x.chunks(3)
+            .map(|chunk| Row::new(chunk.to_vec()))
+            .collect::<Vec<_>>()
+    });
+
+    key_precision.map(|x| {
+        Table::new(x)
+            .style(Style::default().fg(Color::White))
+            .block(Block::default().title("Table"))
+            // Columns widths are constrained in the same way as Layout...
+            .widths(&[
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ])
+    })
+}
+
 pub trait Component<B: Backend> {
     fn render(&mut self, state: &State, f: &mut Frame<B>, rect: Rect);
 }
 
 pub struct TypeTestView<'a> {
     render_cache: Option<Vec<Line<'a>>>,
+    cache_generation: u64,
 }
 
 impl<'a> TypeTestView<'a> {
     pub fn new() -> TypeTestView<'a> {
-        TypeTestView { render_cache: None }
+        TypeTestView {
+            render_cache: None,
+            cache_generation: 0,
+        }
     }
 
-    fn get_keyboard_spans(&self, state: &State) -> Vec<Line<'_>> {
+    fn get_keyboard_spans(&self, state: &State, stats: &StateStats) -> Vec<Line<'_>> {
+        let keyboard = &state.type_test.keyboard;
         let Keyboard {
             layout,
             touch_map,
             active_keys,
-        } = &state.type_test.keyboard;
+            missed_keys,
+            wrong_keys,
+            locked_keys,
+            guide,
+            ..
+        } = keyboard;
 
-        let keys_to_paint = active_keys.keys().collect::<Vec<&char>>();
+        let next_keys = match (guide, state.type_test.expected_char()) {
+            (Guide::Off, _) | (_, None) => Vec::new(),
+            (_, Some(c)) => keyboard.keys_for(c),
+        };
+        let heat = state.heatmap.as_ref().map_or(HashMap::new(), |heatmap| {
+            heatmap.buckets(
+                keyboard,
+                &heatmap::samples(&stats.keys_stats, &state.type_test.actions),
+            )
+        });
 
         let mut lines = Vec::new();
         let mut current_line: Vec<Span<'_>> = Vec::new();
@@ -64,11 +373,22 @@ impl<'a> TypeTestView<'a> {
                 lines.push(Line::from(current_line.clone()));
                 current_line.clear();
             } else {
-                let char_at_i = touch_map.get(&i).unwrap();
                 let mut style = Style::default().fg(Color::White);
 
-                if keys_to_paint.contains(&char_at_i) {
-                    style = style.bg(Color::Blue);
+                if let Some(k) = touch_map.get(&i) {
+                    if wrong_keys.contains_key(k) {
+                        style = style.bg(Color::Red);
+                    } else if missed_keys.contains_key(k) {
+                        style = style.bg(Color::Yellow).fg(Color::Black);
+                    } else if active_keys.contains_key(k) {
+                        style = style.bg(Color::Blue);
+                    } else if next_keys.contains(k) {
+                        style = style.bg(Color::Green).fg(Color::Black);
+                    } else if locked_keys.contains(k) {
+                        style = style.fg(Color::DarkGray);
+                    } else if let Some(bucket) = heat.get(k) {
+                        style = style.bg(HEAT_COLORS[*bucket]).fg(Color::Black);
+                    }
                 }
                 let span = Span::styled(c.to_string(), style);
                 current_line.push(span);
@@ -79,6 +399,43 @@ impl<'a> TypeTestView<'a> {
             lines.push(Line::from(current_line));
         }
 
+        if let Some(heatmap) = &state.heatmap {
+            let title = match (heatmap.metric, heatmap.scope) {
+                (HeatmapMetric::Accuracy, HeatmapScope::Session) => "Accuracy, this session:",
+                (HeatmapMetric::Accuracy, HeatmapScope::History) => "Accuracy, all sessions:",
+                (HeatmapMetric::Speed, HeatmapScope::Session) => "Speed, this session:",
+                (HeatmapMetric::Speed, HeatmapScope::History) => "Speed, all sessions:",
+            };
+            let mut legend = vec![Span::raw(title)];
+            for (label, color) in heatmap.legend().iter().zip(HEAT_COLORS) {
+                legend.push(Span::raw(" "));
+                legend.push(Span::styled(
+                    format!(" {} ", label),
+                    Style::default().bg(color).fg(Color::Black),
+                ));
+            }
+            lines.push(Line::from(""));
+            lines.push(Line::from(legend));
+        }
+
+        if *guide == Guide::Fingers && !next_keys.is_empty() {
+            let fingers: Vec<String> = next_keys
+                .iter()
+                .map(|k| {
+                    let key = &keyboard.keys[*k];
+                    match key.finger {
+                        Some(finger) => format!("{} ({})", key.display(), finger.name()),
+                        None => key.display(),
+                    }
+                })
+                .collect();
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                format!("Next: {}", fingers.join(" + ")),
+                Style::default().fg(Color::Green),
+            )));
+        }
+
         lines
     }
 
@@ -110,43 +467,40 @@ impl<'a> TypeTestView<'a> {
             .collect::<Vec<Line<'_>>>();
 
         self.render_cache = Some(x);
+        self.cache_generation = state.type_test.generation;
+    }
+
+    // Typing a character can change the marks of a whole word, so lines are restyled at once
+    fn restyle_line(&mut self, state: &State, i: usize) {
+        let padding = state.type_test.lines[i].0 as usize;
+        let (marks, insertions) = state.type_test.align_line(i);
+        let spans = &mut self.render_cache.as_mut().unwrap()[i].spans;
+
+        for (j, mark) in marks.into_iter().enumerate() {
+            let span = &mut spans[j + padding];
+            span.style.bg = None;
+            span.style.fg = Some(match mark {
+                _ if insertions[j] > 0 => Color::Magenta,
+                Some(Mark::Correct) => Color::Green,
+                Some(Mark::Substitution) => Color::Red,
+                Some(Mark::Omission) => Color::Yellow,
+                Some(Mark::Transposition) => Color::Cyan,
+                None => Color::White,
+            });
+        }
     }
 }
 
 impl<B: Backend> Component<B> for TypeTestView<'_> {
     fn render(&mut self, state: &State, frame: &mut Frame<B>, _rect: Rect) {
-        if self.render_cache.is_none() {
+        if self.render_cache.is_none() || self.cache_generation != state.type_test.generation {
             self.build_render_cache(state);
         }
 
-        if let Some((i, j)) = state.type_test.last_modified {
-            let padding = state
-                .type_test
-                .lines
-                .get(state.type_test.last_modified.unwrap().0)
-                .unwrap()
-                .0 as usize;
-
-            let span = &mut self.render_cache.as_mut().unwrap()[i].spans[j + padding];
-            span.style.bg = None;
-
-            let elem = &state.type_test.lines[i].1[j];
-
-            match &elem.value {
-                Some(v) if *v == elem.target => {
-                    // Handle the case where the value is equal to the target
-                    span.style.fg = Some(Color::Green);
-                }
-                Some(v) if *v != elem.target => {
-                    // Handle the case where the value is not equal to the target
-                    span.style.fg = Some(Color::Red);
-                }
-                Some(_) => {}
-                None => {
-                    span.style.fg = Some(Color::White);
-                }
-            }
+        if let Some((i, _)) = state.type_test.last_modified {
+            self.restyle_line(state, i);
         }
+        self.restyle_line(state, state.type_test.cursor.0);
 
         let padding = state
             .type_test
@@ -156,9 +510,15 @@ impl<B: Backend> Component<B> for TypeTestView<'_> {
             .0 as usize;
 
         let (i, j) = state.type_test.cursor;
+        // Keys refused by the correction policy are flagged on the cursor
+        let cursor_color = if state.type_test.lines[i].1[j].misses > 0 {
+            Color::Red
+        } else {
+            Color::Blue
+        };
         self.render_cache.as_mut().unwrap()[i].spans[j + padding]
             .style
-            .bg = Some(Color::Blue);
+            .bg = Some(cursor_color);
 
         let _area = frame.size();
 
@@ -205,77 +565,38 @@ impl<B: Backend> Component<B> for TypeTestView<'_> {
         );
 
         let stats = state.type_test.stats();
-        let aps = stats
-            .aps
-            .map_or(String::from("Undefined"), |x| x.to_string());
-        let words_per_minute = stats
-            .words_per_minute
-            .map_or(String::from("Undefined"), |x| x.to_string());
-        let precision = stats
-            .precision
-            .map_or(String::from("Undefined"), |x| (x * 100.0).to_string());
-
-        // sorted keys
-
-        let key_precision = stats.keys_precision.map(|keys_prec| {
-            let x = keys_prec
-                .iter()
-                .map(|(k, v)| {
-                    let mut style = Style::default().fg(Color::White);
-                    if *v > 0.9 {
-                        style = style.fg(Color::Green);
-                    } else if *v > 0.8 {
-                        style = style.fg(Color::Yellow);
-                    } else {
-                        style = style.fg(Color::Red);
-                    }
-                    // format v with 3 decimal places
-                    let e = format!("{:.3}", *v);
-
-                    let p = Span::styled(e, style);
-                    let line = Line::from(vec![
-                        Span::styled(k.to_string(), Style::default().fg(Color::White)),
-                        Span::styled(": ", Style::default().fg(Color::White)),
-                        p,
-                    ]);
-                    Cell::from(line)
-                })
-                .collect::<Vec<Cell<'_>>>();
-
-            x.chunks(3)
-                .map(|chunk| Row::new(chunk.to_vec()))
-                .collect::<Vec<_>>()
-        });
-
-        let kp = key_precision.map(|x| {
-            Table::new(x)
-                .block(Block::default().title("Table"))
-                .style(Style::default().fg(Color::White))
-                .block(Block::default().title("Table"))
-                // Columns widths are constrained in the same way as Layout...
-                .widths(&[
-                    Constraint::Length(10),
-                    Constraint::Length(10),
-                    Constraint::Length(10),
-                ])
-        });
-
-        let stats = vec![
-            Line::from(vec![
-                Span::styled("APS: ", Style::default().fg(Color::White)),
-                Span::styled(aps, Style::default().fg(Color::Green)),
-            ]),
-            Line::from(vec![
-                Span::styled("WPM: ", Style::default().fg(Color::White)),
-                Span::styled(words_per_minute, Style::default().fg(Color::Green)),
-            ]),
-            Line::from(vec![
-                Span::styled("Precision: ", Style::default().fg(Color::White)),
-                Span::styled(precision, Style::default().fg(Color::Green)),
-            ]),
-        ];
+        let kp = keys_precision_table(&stats);
+        let mut stats_text = stats_lines(&stats);
+        if state.type_test.is_paused() {
+            stats_text.push(Line::from(Span::styled(
+                "Paused",
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+        if state.type_test.alignment == AlignmentMode::EditDistance {
+            stats_text.extend(error_kind_lines(&stats));
+        }
+        stats_text.extend(finger_summary_lines(&FingerReport::new(
+            &state.type_test.keyboard,
+            &stats.keys_stats,
+            &state.type_test.actions,
+        )));
+        stats_text.extend(timing_lines(
+            TimingReport::new(&state.type_test.actions).as_ref(),
+        ));
+        if let Some(remaining) = state.type_test.remaining(state.clock.now()) {
+            let style = if remaining < 5000 {
+                Style::default().fg(Color::Red)
+            } else {
+                Style::default().fg(Color::Yellow)
+            };
+            stats_text.push(Line::from(vec![
+                Span::styled("Time left: ", Style::default().fg(Color::White)),
+                Span::styled(format_elapsed(remaining), style),
+            ]));
+        }
 
-        let lines = self.get_keyboard_spans(state);
+        let lines = self.get_keyboard_spans(state, &stats);
 
         frame.render_widget(
             Paragraph::new("")
@@ -292,7 +613,7 @@ impl<B: Backend> Component<B> for TypeTestView<'_> {
         );
 
         frame.render_widget(
-            Paragraph::new(stats).block(Block::default().borders(Borders::ALL)),
+            Paragraph::new(stats_text).block(Block::default().borders(Borders::ALL)),
             second_column[0], // frame.size(),
         );
 
@@ -305,3 +626,487 @@ impl<B: Backend> Component<B> for TypeTestView<'_> {
         }
     }
 }
+
+pub struct ResultsView {}
+
+impl ResultsView {
+    pub fn new() -> ResultsView {
+        ResultsView {}
+    }
+}
+
+impl<B: Backend> Component<B> for ResultsView {
+    fn render(&mut self, state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let stats = state.type_test.stats();
+        let playlist = match &state.source {
+            Some(TextSource::Playlist(playlist)) => Some(playlist),
+            _ => None,
+        };
+        let summary = playlist.and_then(|p| p.summary().map(|s| (p, s)));
+
+        let summary_height = summary
+            .as_ref()
+            .map_or(0, |(p, _)| p.segments.len() as u16 + 4);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(19),
+                    Constraint::Min(0),
+                    Constraint::Length(summary_height),
+                ]
+                .as_ref(),
+            )
+            .split(centered_rect(rect, 60, 80));
+
+        let mut lines = vec![
+            Line::from(Span::styled(
+                "Test complete",
+                Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+        ];
+        if let Some(playlist) = playlist {
+            lines.push(stat_line(
+                "File: ",
+                format!(
+                    "{} of {}, {}",
+                    playlist.current + 1,
+                    playlist.files.len(),
+                    playlist.current_file().map_or("", |f| f.as_str())
+                ),
+            ));
+        }
+        lines.extend(stats_lines(&stats));
+        lines.push(stat_line("Time: ", format_elapsed(stats.elapsed)));
+        lines.push(stat_line("Errors: ", stats.errors.to_string()));
+        if state.type_test.alignment == AlignmentMode::EditDistance {
+            lines.extend(error_kind_lines(&stats));
+        }
+        let report = FingerReport::new(
+            &state.type_test.keyboard,
+            &stats.keys_stats,
+            &state.type_test.actions,
+        );
+        lines.extend(finger_summary_lines(&report));
+        lines.extend(timing_lines(
+            TimingReport::new(&state.type_test.actions).as_ref(),
+        ));
+        if let Some(key) = state.unlocked_key {
+            lines.push(Line::from(Span::styled(
+                format!("New key unlocked: {}", key),
+                Style::default().fg(Color::Green),
+            )));
+        }
+        lines.push(Line::from(""));
+        for error in playlist.map_or(&[][..], |p| &p.skipped) {
+            lines.push(Line::from(Span::styled(
+                format!("Skipped {}", error),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        if let Some(error) = &state.history_error {
+            lines.push(Line::from(Span::styled(
+                format!("Session not saved: {}", error),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        let next = match playlist {
+            Some(playlist) if playlist.next().is_some() => "[n] next file",
+            _ => "[n] new text",
+        };
+        lines.push(Line::from(Span::styled(
+            format!("[r] retry   {}   [h] home   [q] quit", next),
+            Style::default().fg(Color::Blue),
+        )));
+
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::default().borders(Borders::ALL)),
+            layout[0],
+        );
+
+        let tables = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                [
+                    Constraint::Percentage(34),
+                    Constraint::Percentage(33),
+                    Constraint::Percentage(33),
+                ]
+                .as_ref(),
+            )
+            .split(layout[1]);
+        if let Some(kp) = keys_precision_table(&stats) {
+            frame.render_widget(kp.block(Block::default().borders(Borders::ALL)), tables[0]);
+        }
+        frame.render_widget(
+            finger_table(&report).block(Block::default().borders(Borders::ALL)),
+            tables[1],
+        );
+        frame.render_widget(
+            ngram_table(&stats).block(Block::default().borders(Borders::ALL)),
+            tables[2],
+        );
+        if let Some((playlist, summary)) = summary {
+            frame.render_widget(
+                playlist_table(playlist, &summary)
+                    .block(Block::default().borders(Borders::ALL).title("Playlist")),
+                layout[2],
+            );
+        }
+    }
+}
+
+pub struct HomeView {}
+
+impl HomeView {
+    pub fn new() -> HomeView {
+        HomeView {}
+    }
+}
+
+fn menu_label(item: &MenuItem, home: &HomeState) -> String {
+    match item {
+        MenuItem::File => String::from("Practice a file"),
+        MenuItem::Words => String::from("Random words"),
+        MenuItem::Timed => format!("Timed words  < {}s >", home.duration),
+        MenuItem::Lessons => String::from("Lessons, one new key at a time"),
+        MenuItem::Adaptive => String::from("Adaptive practice of your weak spots"),
+        MenuItem::History => String::from("History"),
+        MenuItem::Recent(path) => path.clone(),
+    }
+}
+
+impl<B: Backend> Component<B> for HomeView {
+    fn render(&mut self, state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let home = &state.home;
+        let mut lines = Vec::new();
+
+        for (i, item) in home.items().iter().enumerate() {
+            if matches!(item, MenuItem::Recent(_))
+                && !matches!(home.items()[i - 1], MenuItem::Recent(_))
+            {
+                lines.push(Line::from(""));
+                lines.push(Line::from(Span::styled(
+                    "Recent files",
+                    Style::default().fg(Color::White),
+                )));
+            }
+            let (marker, style) = if i == home.selected {
+                ("> ", Style::default().fg(Color::Black).bg(Color::Green))
+            } else {
+                ("  ", Style::default().fg(Color::Green))
+            };
+            lines.push(Line::from(vec![
+                Span::raw(marker),
+                Span::styled(menu_label(item, home), style),
+            ]));
+        }
+
+        lines.push(Line::from(""));
+        lines.push(stat_line("Layout: ", home.layout.clone()));
+        lines.push(Line::from(vec![
+            Span::styled("Mode: ", Style::default().fg(Color::White)),
+            Span::styled(home.mode.to_string(), Style::default().fg(Color::Green)),
+            Span::styled(
+                " of files and adaptive practice (Tab to change)",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ]));
+        lines.push(Line::from(Span::styled(
+            "[up/down] move   [enter] start   [q] quit",
+            Style::default().fg(Color::Blue),
+        )));
+
+        if let Some(error) = &home.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL).title("blitzkey"))
+                .wrap(Wrap { trim: true }),
+            centered_rect(rect, 60, 60),
+        );
+    }
+}
+
+pub struct PickerView {}
+
+impl PickerView {
+    pub fn new() -> PickerView {
+        PickerView {}
+    }
+}
+
+impl<B: Backend> Component<B> for PickerView {
+    fn render(&mut self, state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let picker = &state.picker;
+        let area = centered_rect(rect, 90, 80);
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(5), Constraint::Length(5)].as_ref())
+            .split(area);
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+            .split(layout[0]);
+
+        let rows: Vec<Row<'_>> = picker
+            .visible()
+            .into_iter()
+            .map(|entry| {
+                let (name, color) = if entry.is_dir {
+                    (format!("{}/", entry.name), Color::Blue)
+                } else {
+                    (entry.name.clone(), Color::White)
+                };
+                let best = picker
+                    .best_wpm(entry)
+                    .map_or(String::new(), |wpm| format!("{:.1}", wpm));
+                Row::new(vec![
+                    Cell::from(name).style(Style::default().fg(color)),
+                    Cell::from(best).style(Style::default().fg(Color::Green)),
+                ])
+            })
+            .collect();
+        let mut table_state = TableState::default().with_selected(Some(picker.selected));
+        frame.render_stateful_widget(
+            Table::new(rows)
+                .header(Row::new(vec!["Name", "Best WPM"]).style(Style::default().fg(Color::Blue)))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(picker.dir.display().to_string()),
+                )
+                .widths(&[Constraint::Min(10), Constraint::Length(9)]),
+            panes[0],
+            &mut table_state,
+        );
+
+        let preview: Vec<Line<'_>> = picker
+            .preview
+            .iter()
+            .map(|line| Line::from(Span::styled(line.clone(), Style::default().fg(Color::Gray))))
+            .collect();
+        frame.render_widget(
+            Paragraph::new(preview).block(Block::default().borders(Borders::ALL).title("Preview")),
+            panes[1],
+        );
+
+        let filter = picker
+            .filter
+            .as_ref()
+            .map_or(String::from("all files"), |e| format!("*.{}", e));
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled("Filter: ", Style::default().fg(Color::White)),
+                Span::styled(filter, Style::default().fg(Color::Green)),
+                Span::styled("   Mode: ", Style::default().fg(Color::White)),
+                Span::styled(state.home.mode.to_string(), Style::default().fg(Color::Green)),
+            ]),
+            Line::from(Span::styled(
+                "[enter] open   [backspace] parent   [left/right] filter   [tab] mode   [p] type a path   [q] back",
+                Style::default().fg(Color::Blue),
+            )),
+        ];
+        if let Some(error) = &picker.error {
+            lines.push(Line::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL))
+                .wrap(Wrap { trim: true }),
+            layout[1],
+        );
+    }
+}
+
+pub struct PathInputView {}
+
+impl PathInputView {
+    pub fn new() -> PathInputView {
+        PathInputView {}
+    }
+}
+
+impl<B: Backend> Component<B> for PathInputView {
+    fn render(&mut self, state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let mut lines = vec![
+            Line::from(Span::styled(
+                "Path to practice text:",
+                Style::default().fg(Color::White),
+            )),
+            Line::from(vec![
+                Span::styled(state.home.path.clone(), Style::default().fg(Color::Green)),
+                Span::styled(" ", Style::default().bg(Color::Blue)),
+            ]),
+            Line::from(""),
+            Line::from(vec![
+                Span::styled("Mode: ", Style::default().fg(Color::White)),
+                Span::styled(
+                    state.home.mode.to_string(),
+                    Style::default().fg(Color::Green),
+                ),
+                Span::styled(" (Tab to change)", Style::default().fg(Color::DarkGray)),
+            ]),
+        ];
+
+        if let Some(error) = &state.home.error {
+            lines.push(Line::from(""));
+            lines.push(Line::from(Span::styled(
+                error.clone(),
+                Style::default().fg(Color::Red),
+            )));
+        }
+
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL).title("blitzkey"))
+                .wrap(Wrap { trim: true }),
+            centered_rect(rect, 60, 30),
+        );
+    }
+}
+
+pub struct HistoryView {}
+
+impl HistoryView {
+    pub fn new() -> HistoryView {
+        HistoryView {}
+    }
+}
+
+impl<B: Backend> Component<B> for HistoryView {
+    fn render(&mut self, state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let area = centered_rect(rect, 80, 80);
+        // Latest first, as many as fit
+        let rows: Vec<Row<'_>> = state
+            .home
+            .sessions
+            .iter()
+            .rev()
+            .take(area.height.saturating_sub(4) as usize)
+            .map(|session| {
+                let started_at = Local
+                    .timestamp_millis_opt(session.started_at as i64)
+                    .single()
+                    .map_or(String::new(), |d| d.format("%Y-%m-%d %H:%M").to_string());
+                Row::new(vec![
+                    started_at,
+                    session.mode.to_string(),
+                    session
+                        .words_per_minute
+                        .map_or(String::from("-"), |w| format!("{:.1}", w)),
+                    percent(session.precision),
+                    session.source.clone(),
+                ])
+            })
+            .collect();
+
+        let title = format!(
+            "History, {} sessions ([enter] back)",
+            state.home.sessions.len()
+        );
+        frame.render_widget(
+            Table::new(rows)
+                .header(
+                    Row::new(vec!["Date", "Mode", "WPM", "Accuracy", "Source"])
+                        .style(Style::default().fg(Color::Blue)),
+                )
+                .style(Style::default().fg(Color::White))
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .widths(&[
+                    Constraint::Length(17),
+                    Constraint::Length(9),
+                    Constraint::Length(6),
+                    Constraint::Length(9),
+                    Constraint::Min(10),
+                ]),
+            area,
+        );
+    }
+}
+
+// Renders the view on top of the stack, and the quit confirmation over it
+pub struct Router<'a> {
+    home: HomeView,
+    picker: PickerView,
+    path_input: PathInputView,
+    type_test: TypeTestView<'a>,
+    results: ResultsView,
+    history: HistoryView,
+    confirm_quit: ConfirmQuitView,
+}
+
+impl Router<'_> {
+    pub fn new() -> Self {
+        Router {
+            home: HomeView::new(),
+            picker: PickerView::new(),
+            path_input: PathInputView::new(),
+            type_test: TypeTestView::new(),
+            results: ResultsView::new(),
+            history: HistoryView::new(),
+            confirm_quit: ConfirmQuitView::new(),
+        }
+    }
+
+    pub fn render<B: Backend>(&mut self, state: &State, frame: &mut Frame<B>) {
+        let rect = frame.size();
+        let view: &mut dyn Component<B> = match state.current_view() {
+            View::Home => &mut self.home,
+            View::Picker => &mut self.picker,
+            View::PathInput => &mut self.path_input,
+            View::TypeTest => &mut self.type_test,
+            View::Results => &mut self.results,
+            View::History => &mut self.history,
+        };
+        view.render(state, frame, rect);
+
+        if state.confirm_quit {
+            self.confirm_quit.render(state, frame, rect);
+        }
+    }
+}
+
+pub struct ConfirmQuitView {}
+
+impl ConfirmQuitView {
+    pub fn new() -> ConfirmQuitView {
+        ConfirmQuitView {}
+    }
+}
+
+impl<B: Backend> Component<B> for ConfirmQuitView {
+    fn render(&mut self, _state: &State, frame: &mut Frame<B>, rect: Rect) {
+        let area = centered_rect(rect, 30, 20);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(""),
+                Line::from(Span::styled(
+                    "Quit blitzkey?",
+                    Style::default().fg(Color::White),
+                )),
+                Line::from(Span::styled(
+                    "[y] quit   any other key to cancel",
+                    Style::default().fg(Color::Blue),
+                )),
+            ])
+            .alignment(ratatui::layout::Alignment::Center)
+            .block(Block::default().borders(Borders::ALL)),
+            area,
+        );
+    }
+}
@@ -9,3 +9,50 @@ pub fn read_file_to_string(path: &str) -> io::Result<String> {
     file.read_to_string(&mut contents)?;
     Ok(contents)
 }
+
+// Sources are recorded as absolute paths so history lookups don't depend on the working directory.
+pub fn canonical_source(path: &str) -> String {
+    std::fs::canonicalize(path).map_or(path.to_string(), |p| p.display().to_string())
+}
+
+// Piped text may carry terminal formatting: colour escape sequences, or the backspace
+// overstrikes of `man`. Only the characters that print are kept, with newlines and tabs.
+pub fn strip_formatting(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Control sequences end on a letter or one of @[\]^_`{|}~
+                if chars.next() == Some('[') {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\x08' => {
+                plain.pop();
+            }
+            '\n' | '\t' => plain.push(c),
+            c if c.is_control() => {}
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_formatting() {
+        assert_eq!(
+            strip_formatting("\x1b[1;31mred\x1b[m text\r\n"),
+            "red text\n"
+        );
+        assert_eq!(strip_formatting("N\x08NAME\tb\x08_"), "NAME\t_");
+    }
+}
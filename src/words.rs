@@ -0,0 +1,205 @@
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+pub const WORD_LISTS: [(&str, &str); 6] = [
+    (
+        "english_200",
+        include_str!("../resources/words/english_200.txt"),
+    ),
+    (
+        "english_1k",
+        include_str!("../resources/words/english_1k.txt"),
+    ),
+    (
+        "english_10k",
+        include_str!("../resources/words/english_10k.txt"),
+    ),
+    (
+        "french_200",
+        include_str!("../resources/words/french_200.txt"),
+    ),
+    (
+        "german_200",
+        include_str!("../resources/words/german_200.txt"),
+    ),
+    (
+        "spanish_200",
+        include_str!("../resources/words/spanish_200.txt"),
+    ),
+];
+
+pub const DEFAULT_WORD_LIST: &str = "english_200";
+
+// Generated lines are wrapped around this width
+const LINE_WIDTH: usize = 60;
+
+// Generous upper bound on typing speed used to size the text of a timed test
+const MAX_WORDS_PER_MINUTE: u64 = 250;
+
+pub fn word_list(name: &str) -> Option<Vec<&'static str>> {
+    WORD_LISTS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, words)| words.lines().filter(|w| !w.is_empty()).collect())
+}
+
+pub fn words_for_duration(seconds: u64) -> usize {
+    (seconds * MAX_WORDS_PER_MINUTE / 60) as usize
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordsOptions {
+    pub list: String,
+    pub count: usize,
+    pub punctuation: bool,
+    pub numbers: bool,
+    pub capitalize: bool,
+    pub seed: u64,
+}
+
+fn capitalized(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub fn wrap(words: Vec<String>) -> String {
+    let mut text = String::new();
+    let mut line_len = 0;
+
+    for word in words {
+        let len = word.chars().count();
+        if line_len != 0 && line_len + 1 + len > LINE_WIDTH {
+            text.push('\n');
+            line_len = 0;
+        } else if line_len != 0 {
+            text.push(' ');
+            line_len += 1;
+        }
+        text.push_str(&word);
+        line_len += len;
+    }
+
+    text
+}
+
+impl WordsOptions {
+    pub fn describe(&self) -> String {
+        let mut description = format!("words:{}:{}:seed={}", self.list, self.count, self.seed);
+        for (flag, name) in [
+            (self.punctuation, "punctuation"),
+            (self.numbers, "numbers"),
+            (self.capitalize, "capitalize"),
+        ] {
+            if flag {
+                description.push(':');
+                description.push_str(name);
+            }
+        }
+        description
+    }
+
+    pub fn generate(&self) -> Option<String> {
+        let list = word_list(&self.list)?;
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+
+        let mut words = Vec::with_capacity(self.count);
+        let mut sentence_start = true;
+
+        for i in 0..self.count {
+            let mut word = if self.numbers && rng.gen_bool(0.1) {
+                rng.gen_range(0..10000).to_string()
+            } else {
+                list.choose(&mut rng).unwrap().to_string()
+            };
+
+            if (self.punctuation && sentence_start) || (self.capitalize && rng.gen_bool(0.2)) {
+                word = capitalized(&word);
+            }
+            sentence_start = false;
+
+            if self.punctuation {
+                let last = i == self.count - 1;
+                let roll: f64 = rng.gen();
+                if last || roll < 0.08 {
+                    word.push(*['.', '.', '.', '?', '!'].choose(&mut rng).unwrap());
+                    sentence_start = true;
+                } else if roll < 0.18 {
+                    word.push(',');
+                } else if roll < 0.20 {
+                    word.push(*[';', ':'].choose(&mut rng).unwrap());
+                } else if roll < 0.23 {
+                    word = format!("\"{}\"", word);
+                } else if roll < 0.25 {
+                    word = format!("({})", word);
+                }
+            }
+
+            words.push(word);
+        }
+
+        Some(wrap(words))
+    }
+
+    // The same options with a fresh seed, for the next text of a session
+    pub fn reseeded(&self) -> WordsOptions {
+        WordsOptions {
+            seed: ChaCha8Rng::seed_from_u64(self.seed).gen(),
+            ..self.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> WordsOptions {
+        WordsOptions {
+            list: String::from("english_1k"),
+            count: 50,
+            punctuation: false,
+            numbers: false,
+            capitalize: false,
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn word_lists_are_embedded() {
+        for (name, size) in [
+            ("english_200", 200),
+            ("english_1k", 1000),
+            ("english_10k", 10000),
+        ] {
+            assert_eq!(word_list(name).unwrap().len(), size);
+        }
+        assert!(word_list("klingon_200").is_none());
+    }
+
+    #[test]
+    fn same_seed_same_text() {
+        let text = options().generate().unwrap();
+        assert_eq!(text, options().generate().unwrap());
+        assert_eq!(text.split_whitespace().count(), 50);
+        assert!(text.lines().all(|l| l.chars().count() <= LINE_WIDTH));
+
+        let other = options().reseeded().generate().unwrap();
+        assert_ne!(text, other);
+    }
+
+    #[test]
+    fn punctuation_ends_sentences() {
+        let text = WordsOptions {
+            punctuation: true,
+            ..options()
+        }
+        .generate()
+        .unwrap();
+
+        assert!(text.starts_with(|c: char| c.is_uppercase()));
+        assert!(text.ends_with(['.', '?', '!']));
+    }
+}